@@ -0,0 +1,214 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Attachment utilities
+//!
+//! Files, e.g. logs or screenshots, may be attached to a message by storing
+//! them as blobs in a dedicated directory in the message's tree. Replies may
+//! inherit the tree of the message replied to. However, the attachment
+//! directory is never inherited, i.e. only the files attached to a message
+//! itself reside in its attachment directory.
+//!
+
+use git2::{self, Commit, Oid, Tree};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Name of the directory holding attachments in a message's tree
+///
+pub const ATTACHMENT_DIR: &str = ".dit-attachments";
+
+
+/// Representation of a file attached to a message
+///
+pub struct Attachment<'r> {
+    name: String,
+    blob: git2::Blob<'r>,
+}
+
+impl<'r> Attachment<'r> {
+    /// Get the attachment's file name
+    ///
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// Get the attachment's size in bytes
+    ///
+    pub fn size(&self) -> usize {
+        self.blob.content().len()
+    }
+
+    /// Get the attachment's content
+    ///
+    pub fn content(&self) -> &[u8] {
+        self.blob.content()
+    }
+}
+
+
+/// Get the files attached to a message
+///
+pub fn attachments<'r>(repo: &'r git2::Repository, message: &Commit) -> Result<Vec<Attachment<'r>>> {
+    let tree = message.tree().chain_err(|| EK::CannotGetCommit)?;
+    let dir = match tree.get_name(ATTACHMENT_DIR) {
+        Some(entry) => entry
+            .to_object(repo)
+            .chain_err(|| EK::CannotGetCommit)?
+            .into_tree()
+            .map_err(|_| Error::from_kind(EK::CannotGetCommit))?,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut retval = Vec::new();
+    for entry in dir.iter() {
+        if entry.kind() != Some(git2::ObjectType::Blob) {
+            continue;
+        }
+        let blob = entry
+            .to_object(repo)
+            .chain_err(|| EK::CannotGetCommit)?
+            .into_blob()
+            .map_err(|_| Error::from_kind(EK::CannotGetCommit))?;
+        retval.push(Attachment { name: entry.name().unwrap_or_default().to_owned(), blob });
+    }
+    Ok(retval)
+}
+
+
+/// Build a tree for a new message
+///
+/// The tree returned contains the entries of the base tree supplied, if any,
+/// except for its attachment directory. The blobs supplied are placed in the
+/// tree's attachment directory under the names supplied.
+///
+pub fn message_tree<'r, I>(repo: &'r git2::Repository, base: Option<&Tree>, attachments: I) -> Result<Tree<'r>>
+    where I: IntoIterator<Item = (String, Oid)>
+{
+    let mut builder = repo.treebuilder(base).chain_err(|| EK::CannotBuildTree)?;
+    if builder.get(ATTACHMENT_DIR).chain_err(|| EK::CannotBuildTree)?.is_some() {
+        builder.remove(ATTACHMENT_DIR).chain_err(|| EK::CannotBuildTree)?;
+    }
+
+    let mut dir = repo.treebuilder(None).chain_err(|| EK::CannotBuildTree)?;
+    for (name, blob) in attachments {
+        dir.insert(name, blob, 0o100644).chain_err(|| EK::CannotBuildTree)?;
+    }
+    if !dir.is_empty() {
+        let dir = dir.write().chain_err(|| EK::CannotBuildTree)?;
+        builder.insert(ATTACHMENT_DIR, dir, 0o040000).chain_err(|| EK::CannotBuildTree)?;
+    }
+
+    builder
+        .write()
+        .and_then(|id| repo.find_tree(id))
+        .chain_err(|| EK::CannotBuildTree)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    // attachment tests
+
+    #[test]
+    fn message_attachments() {
+        let mut testing_repo = TestingRepo::new("message_attachments");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+
+        let blob = repo.blob(b"foo").expect("Could not create blob");
+        let tree = message_tree(repo, None, vec![("foo.log".to_owned(), blob)])
+            .expect("Could not build tree");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        let files = attachments(repo, &initial_message).expect("Could not get attachments");
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name(), "foo.log");
+        assert_eq!(files[0].size(), 3);
+        assert_eq!(files[0].content(), b"foo");
+
+        // attachments are not inherited
+        let tree = message_tree(repo, Some(&tree), vec![])
+            .expect("Could not build tree");
+        assert_eq!(tree.id(), repo.empty_tree().expect("Could not create empty tree").id());
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &tree, vec![&initial_message])
+            .expect("Could not add message");
+        assert!(attachments(repo, &message).expect("Could not get attachments").is_empty());
+    }
+
+    #[test]
+    fn child_attachments() {
+        let mut testing_repo = TestingRepo::new("child_attachments");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+
+        // the parent's tree holds both an ordinary file and an attachment
+        let file = repo.blob(b"bar").expect("Could not create blob");
+        let mut builder = repo.treebuilder(None).expect("Could not create tree builder");
+        builder.insert("bar.txt", file, 0o100644).expect("Could not insert file");
+        let base = builder
+            .write()
+            .and_then(|id| repo.find_tree(id))
+            .expect("Could not write tree");
+        let blob = repo.blob(b"foo").expect("Could not create blob");
+        let tree = message_tree(repo, Some(&base), vec![("foo.log".to_owned(), blob)])
+            .expect("Could not build tree");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &tree, vec![])
+            .expect("Could not create issue");
+        let parent = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        // a child message inherits the file but not the parent's attachments
+        let parent_tree = parent.tree().expect("Could not get tree");
+        let tree = message_tree(repo, Some(&parent_tree), vec![])
+            .expect("Could not build tree");
+        let child = issue
+            .add_message(&sig, &sig, "Test message 2", &tree, vec![&parent])
+            .expect("Could not add message");
+        let child_tree = child.tree().expect("Could not get tree");
+        assert!(child_tree.get_name("bar.txt").is_some());
+        assert!(child_tree.get_name(ATTACHMENT_DIR).is_none());
+        assert!(attachments(repo, &child).expect("Could not get attachments").is_empty());
+
+        // a child message with attachments of its own only holds those
+        let blob = repo.blob(b"baz").expect("Could not create blob");
+        let tree = message_tree(repo, Some(&parent_tree), vec![("baz.log".to_owned(), blob)])
+            .expect("Could not build tree");
+        let child = issue
+            .add_message(&sig, &sig, "Test message 3", &tree, vec![&parent])
+            .expect("Could not add message");
+        let names: Vec<String> = attachments(repo, &child)
+            .expect("Could not get attachments")
+            .iter()
+            .map(|attachment| attachment.name().to_owned())
+            .collect();
+        assert_eq!(names, vec!["baz.log".to_owned()]);
+    }
+}
@@ -0,0 +1,268 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Backlink utilities
+//!
+//! Messages may refer to other messages or commits either via parents other
+//! than the first one or via trailers, e.g. "Fixes" or "Dit-depends-on". This
+//! module provides utilities for finding the messages referring to a given set
+//! of objects.
+//!
+
+use git2::{self, Oid};
+use std::collections::HashSet;
+
+use issue::IssueRefType;
+use message::Message;
+use trailer::spec::REFERENCE_SPECS;
+use utils::resolve_object_id;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Minimal length of an abbreviated id in a trailer considered a reference
+///
+/// Abbreviated ids are only considered in trailers explicitly referring to
+/// commits or issues, e.g. "Fixes" or "Dit-ref".
+///
+const MIN_ABBREV_LEN: usize = 7;
+
+
+/// Kind of a reference from a message to an object
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkKind {
+    /// The object is a parent of the message
+    Parent,
+    /// The object is referred to by a trailer with the key included
+    Trailer(String),
+}
+
+
+/// Reference from a message to an object
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backlink {
+    /// The message referring to the object
+    pub message: Oid,
+    /// The object referred to
+    pub target: Oid,
+    /// The kind of reference
+    pub kind: LinkKind,
+}
+
+
+/// Find all messages referring to any of the objects supplied
+///
+/// This function considers all messages reachable from dit references, local
+/// and remote, and archive references using a single revwalk. A message refers
+/// to an object if the object is a parent other than the first one or, for
+/// initial messages, any parent. A message also refers to an object if the
+/// value of one of its trailers contains the object's full id or if the value
+/// of a trailer explicitly referring to commits or issues, e.g. "Fixes" or
+/// "Dit-ref", contains the object's abbreviated id. In any case, the id needs
+/// to resolve to the object in the repository. For references to issues in
+/// other repositories, only the part following the `#` is considered.
+///
+pub fn backlinks<I>(repo: &git2::Repository, targets: I) -> Result<Vec<Backlink>>
+    where I: IntoIterator<Item = Oid>
+{
+    let targets: HashSet<Oid> = targets.into_iter().collect();
+
+    // We walk the messages in topological order, tracking which commits are
+    // messages. A commit is a message if it is referred to by a dit reference
+    // or if it is the first parent of a message other than an initial message.
+    let mut revwalk = repo.revwalk().chain_err(|| EK::CannotConstructRevwalk)?;
+    revwalk.simplify_first_parent();
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL);
+    let mut messages = HashSet::new();
+    let mut initial_messages = HashSet::new();
+
+    // Messages of archived leaves are reachable via the parents of the archive
+    // commits.
+    for glob in &["refs/dit/**", "refs/remotes/*/dit/**", "refs/dit-archive/*"] {
+        let refs = repo
+            .references_glob(glob)
+            .chain_err(|| EK::CannotGetReferences(glob.to_string()))?;
+        for item in refs {
+            let reference = item.chain_err(|| EK::CannotGetReference)?;
+            let commit = reference
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .into_commit()
+                .map_err(|_| Error::from_kind(EK::CannotGetCommit))?;
+
+            if let Some((id, IssueRefType::Head)) = reference.name().and_then(IssueRefType::of_ref) {
+                initial_messages.insert(id);
+            }
+
+            let tips: Vec<Oid> = if reference.name().unwrap_or_default().starts_with("refs/dit-archive/") {
+                commit.parent_ids().collect()
+            } else {
+                vec![commit.id()]
+            };
+            for tip in tips {
+                revwalk.push(tip).chain_err(|| EK::CannotConstructRevwalk)?;
+                messages.insert(tip);
+            }
+        }
+    }
+
+    // Initial messages may refer to commits in the regular history via their
+    // first parent. We don't need to walk the history of branches and tags.
+    for glob in &["heads", "tags"] {
+        revwalk.hide_glob(glob).chain_err(|| EK::CannotConstructRevwalk)?;
+    }
+
+    let mut retval = Vec::new();
+    for item in revwalk {
+        let message = item
+            .and_then(|id| repo.find_commit(id))
+            .chain_err(|| EK::CannotGetCommit)?;
+        if !messages.contains(&message.id()) {
+            continue;
+        }
+        if !initial_messages.contains(&message.id()) {
+            messages.extend(message.parent_id(0).ok());
+        }
+
+        let skip = if initial_messages.contains(&message.id()) { 0 } else { 1 };
+        retval.extend(message
+            .parent_ids()
+            .skip(skip)
+            .filter(|parent| targets.contains(parent))
+            .map(|parent| Backlink { message: message.id(), target: parent, kind: LinkKind::Parent })
+        );
+
+        for trailer in message.trailers() {
+            let key = trailer.key.as_ref().to_owned();
+            let is_reference = REFERENCE_SPECS.iter().any(|spec| spec.key == key);
+            let value = trailer.value.to_string();
+            let words = value
+                .split_whitespace()
+                .map(|word| word.rsplit('#').next().unwrap_or(word))
+                .filter(|word| word.len() == 40 || (is_reference && word.len() >= MIN_ABBREV_LEN));
+            for word in words {
+                let target = resolve_object_id(repo, word)
+                    .and_then(|object| object.peel(git2::ObjectType::Commit).ok())
+                    .map(|commit| commit.id())
+                    .filter(|id| targets.contains(id));
+                if let Some(target) = target {
+                    retval.push(Backlink {
+                        message: message.id(),
+                        target,
+                        kind: LinkKind::Trailer(key.clone()),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(retval)
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    // backlinks tests
+
+    #[test]
+    fn find_backlinks() {
+        let mut testing_repo = TestingRepo::new("find_backlinks");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let commit = repo
+            .commit(None, &sig, &sig, "Test commit", &empty_tree, &[])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![&commit])
+            .expect("Could not create issue");
+        let initial_message1 = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue1
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message1, &commit])
+            .expect("Could not add message");
+
+        let short_id = &commit.id().to_string()[..8];
+        let issue2 = repo
+            .create_issue(&sig, &sig, format!("Test message 3\n\nFixes: {}", short_id), &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message2 = issue2
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message2 = issue2
+            .add_message(&sig, &sig, format!("Test message 4\n\nDit-ref: origin#{}", issue1.id()), &empty_tree, vec![&initial_message2])
+            .expect("Could not add message");
+
+        let mut links = backlinks(repo, vec![commit.id()]).expect("Could not find backlinks");
+        links.sort_by_key(|link| link.message);
+        let mut expected = vec![
+            Backlink { message: issue1.id(), target: commit.id(), kind: LinkKind::Parent },
+            Backlink { message: message1.id(), target: commit.id(), kind: LinkKind::Parent },
+            Backlink { message: issue2.id(), target: commit.id(), kind: LinkKind::Trailer("Fixes".to_owned()) },
+        ];
+        expected.sort_by_key(|link| link.message);
+        assert_eq!(links, expected);
+
+        let links = backlinks(repo, vec![issue1.id()]).expect("Could not find backlinks");
+        assert_eq!(links, vec![
+            Backlink { message: message2.id(), target: issue1.id(), kind: LinkKind::Trailer("Dit-ref".to_owned()) },
+        ]);
+    }
+
+    #[test]
+    fn explicit_backlinks() {
+        let mut testing_repo = TestingRepo::new("explicit_backlinks");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let commit = repo
+            .commit(None, &sig, &sig, "Test commit", &empty_tree, &[])
+            .expect("Could not create commit");
+        let short_id = &commit.to_string()[..8];
+
+        // abbreviated ids in other trailers and unknown ids are ignored
+        let message = format!("Test message 1\n\nSee-also: {}\nFixes: deadbeef\nFixes: {}",
+                              short_id,
+                              "0123456789abcdef0123456789abcdef01234567");
+        repo.create_issue(&sig, &sig, message, &empty_tree, vec![])
+            .expect("Could not create issue");
+        assert!(backlinks(repo, vec![commit]).expect("Could not find backlinks").is_empty());
+
+        // full ids are considered in any trailer
+        let issue = repo
+            .create_issue(&sig, &sig, format!("Test message 2\n\nSee-also: {}", commit), &empty_tree, vec![])
+            .expect("Could not create issue");
+        assert_eq!(backlinks(repo, vec![commit]).expect("Could not find backlinks"), vec![
+            Backlink { message: issue.id(), target: commit, kind: LinkKind::Trailer("See-also".to_owned()) },
+        ]);
+    }
+}
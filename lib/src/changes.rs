@@ -0,0 +1,265 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Changes to issues
+//!
+//! This module provides functionality for translating updates of dit
+//! references, e.g. performed during a fetch, into changes of issues: new
+//! issues, new messages, head references moved and issues which are no longer
+//! referenced. The references are expected to be updated already when the
+//! changes are computed.
+//!
+
+use git2::{self, Oid};
+use std::collections::HashMap;
+
+use issue::{Issue, IssueRefType};
+use iter::MessagesExt;
+use repository::RepositoryExt;
+use trailer::spec::ISSUE_STATUS_SPEC;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Update of a reference
+///
+/// A zero id denotes a reference which did not exist before or was deleted.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefUpdate {
+    pub refname: String,
+    pub old: Oid,
+    pub new: Oid,
+}
+
+impl RefUpdate {
+    pub fn new<R: Into<String>>(refname: R, old: Oid, new: Oid) -> Self {
+        RefUpdate { refname: refname.into(), old, new }
+    }
+}
+
+
+/// Move of a head reference
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadMove {
+    pub refname: String,
+    pub old: Oid,
+    pub new: Oid,
+    /// Status of the issue accumulated from the old and new head, if changed
+    pub status: Option<(Option<String>, Option<String>)>,
+}
+
+
+/// Changes of a single issue
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IssueChanges {
+    /// Id of the issue
+    pub issue: Oid,
+    /// Whether the issue was not known before
+    pub is_new: bool,
+    /// Messages which were not reachable before, excluding a new issue's
+    /// initial message
+    pub new_messages: Vec<Oid>,
+    /// Head references moved
+    pub head_moves: Vec<HeadMove>,
+    /// Whether no head reference remains for the issue
+    pub is_removed: bool,
+}
+
+
+/// Compute the changes of issues caused by reference updates
+///
+/// Updates of references which are not dit references are ignored. Changes are
+/// returned in the order in which the issues first appear in the updates.
+///
+pub fn issue_changes<'a, I>(repo: &git2::Repository, updates: I) -> Result<Vec<IssueChanges>>
+    where I: IntoIterator<Item = &'a RefUpdate>
+{
+    // group the updates by issue
+    let mut order = Vec::new();
+    let mut grouped: HashMap<Oid, Vec<&RefUpdate>> = HashMap::new();
+    for update in updates {
+        if let Some((id, _)) = IssueRefType::of_ref(&update.refname) {
+            if !grouped.contains_key(&id) {
+                order.push(id);
+            }
+            grouped.entry(id).or_default().push(update);
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|id| changes_of_issue(repo, id, &grouped[&id]))
+        .collect()
+}
+
+
+/// Compute the changes of a single issue
+///
+fn changes_of_issue(repo: &git2::Repository, id: Oid, updates: &[&RefUpdate]) -> Result<IssueChanges> {
+    let mut retval = IssueChanges {
+        issue: id,
+        is_new: false,
+        new_messages: Vec::new(),
+        head_moves: Vec::new(),
+        is_removed: false,
+    };
+
+    let issue = match repo.find_issue(id) {
+        Ok(issue) => issue,
+        Err(_) => {
+            // the initial message is not available
+            retval.is_removed = true;
+            return Ok(retval);
+        },
+    };
+    retval.is_removed = issue.heads()?.next().is_none();
+
+    // Messages are new if they are reachable from the new tips but not from
+    // the old tips or references which were not updated.
+    let mut messages = issue.terminated_messages()?;
+    let mut any_new = false;
+    for update in updates.iter().filter(|update| !update.new.is_zero()) {
+        messages.revwalk.push(update.new).chain_err(|| EK::CannotConstructRevwalk)?;
+        any_new = true;
+    }
+    for update in updates.iter().filter(|update| !update.old.is_zero()) {
+        hide(&mut messages.revwalk, update.old)?;
+    }
+    for reference in issue.all_refs(IssueRefType::Any)? {
+        let reference = reference.chain_err(|| EK::CannotGetReference)?;
+        let unchanged = updates
+            .iter()
+            .all(|update| Some(update.refname.as_ref()) != reference.name());
+        if unchanged {
+            let target = reference.peel(git2::ObjectType::Commit).chain_err(|| EK::CannotGetCommit)?;
+            hide(&mut messages.revwalk, target.id())?;
+        }
+    }
+    if any_new {
+        for message in messages {
+            let message = message?;
+            if message.id() == id {
+                retval.is_new = true;
+            } else {
+                retval.new_messages.push(message.id());
+            }
+        }
+    }
+
+    for update in updates.iter() {
+        let is_head = IssueRefType::of_ref(&update.refname)
+            .map(|(_, ref_type)| ref_type == IssueRefType::Head)
+            .unwrap_or(false);
+        if !is_head || update.new.is_zero() {
+            continue;
+        }
+
+        let old_status = status(&issue, update.old)?;
+        let new_status = status(&issue, update.new)?;
+        retval.head_moves.push(HeadMove {
+            refname: update.refname.clone(),
+            old: update.old,
+            new: update.new,
+            status: if old_status != new_status { Some((old_status, new_status)) } else { None },
+        });
+    }
+
+    Ok(retval)
+}
+
+
+/// Hide a commit from a revwalk, if it exists
+///
+/// Old tips may no longer be available, e.g. after a forced update.
+///
+fn hide(revwalk: &mut git2::Revwalk, id: Oid) -> Result<()> {
+    match revwalk.hide(id) {
+        Err(ref err) if err.code() == git2::ErrorCode::NotFound => Ok(()),
+        result => result.chain_err(|| EK::CannotConstructRevwalk),
+    }
+}
+
+
+/// Get the status of an issue accumulated from a specific head
+///
+fn status(issue: &Issue, head: Oid) -> Result<Option<String>> {
+    if head.is_zero() {
+        return Ok(None);
+    }
+    let messages = issue
+        .messages_from(head)?
+        .collect::<Result<Vec<_>>>()?;
+    let acc: HashMap<_, _> = messages.into_iter().accumulate_trailers(&[ISSUE_STATUS_SPEC]);
+    Ok(acc
+        .get(ISSUE_STATUS_SPEC.key)
+        .and_then(|values| values.clone().into_iter().next())
+        .map(|value| value.to_string()))
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // Changes tests
+
+    #[test]
+    fn changes_from_updates() {
+        let mut testing_repo = TestingRepo::new("changes_from_updates");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let head = format!("refs/dit/{}/head", issue.id());
+
+        // a new issue
+        let updates = vec![RefUpdate::new(head.as_str(), Oid::zero(), issue.id())];
+        let changes = issue_changes(repo, &updates).expect("Could not compute changes");
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_new);
+        assert!(changes[0].new_messages.is_empty());
+        assert_eq!(changes[0].head_moves.len(), 1);
+        assert_eq!(changes[0].head_moves[0].status, None);
+
+        // a reply moving the head and changing the status
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2\n\nDit-status: closed", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue.update_head(message.id(), true).expect("Could not update head");
+        let leaf = format!("refs/dit/{}/leaves/{}", issue.id(), message.id());
+        let updates = vec![
+            RefUpdate::new(head.as_str(), issue.id(), message.id()),
+            RefUpdate::new(leaf.as_str(), Oid::zero(), message.id()),
+            RefUpdate::new("refs/heads/master", Oid::zero(), message.id()),
+        ];
+        let changes = issue_changes(repo, &updates).expect("Could not compute changes");
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].is_new);
+        assert_eq!(changes[0].new_messages, vec![message.id()]);
+        assert_eq!(changes[0].head_moves[0].status, Some((None, Some("closed".to_owned()))));
+        assert!(!changes[0].is_removed);
+    }
+}
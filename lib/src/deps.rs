@@ -0,0 +1,380 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Issue dependency utilities
+//!
+//! Issues may state dependencies on other issues via `Dit-depends-on`
+//! trailers. Conversely, an issue may state that it blocks other issues via
+//! `Dit-blocks` trailers. This module provides a graph type for resolving
+//! those relations across issues.
+//!
+//! Issues are referred to by the (abbreviated) id of their initial message or
+//! any other of their messages. Symbolic names, e.g. branch names, are not
+//! resolved.
+//!
+
+use git2::{self, Commit, Oid};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use issue::Issue;
+use iter::MessagesExt;
+use message::Message;
+use repository::RepositoryExt;
+use trailer::spec::{BLOCKS_SPEC, DEPENDS_ON_SPEC, ISSUE_STATUS_SPEC};
+use utils::resolve_object_id;
+
+use error::*;
+
+
+/// Graph of dependencies between issues
+///
+/// The graph maps each issue to the set of issues it depends on. Dependencies
+/// stated via `Dit-blocks` trailers are stored as dependencies of the blocked
+/// issue.
+///
+pub struct DependencyGraph<'r> {
+    repo: &'r git2::Repository,
+    dependencies: HashMap<Oid, BTreeSet<Oid>>,
+}
+
+impl<'r> DependencyGraph<'r> {
+    /// Create a new, empty dependency graph
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Self {
+        DependencyGraph { repo, dependencies: HashMap::new() }
+    }
+
+    /// Add the relations stated by an issue's messages
+    ///
+    /// Usually, the messages will be those on the first-parent chain of the
+    /// issue's selected head. Only the first word of a trailer's value is
+    /// considered. Values which cannot be resolved to an issue are ignored.
+    ///
+    pub fn add_issue<I>(&mut self, issue: Oid, messages: I)
+        where I: IntoIterator<Item = Commit<'r>>
+    {
+        self.dependencies.entry(issue).or_default();
+
+        for message in messages {
+            for trailer in message.trailers() {
+                let other = match self.resolve(&trailer.value.to_string()) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                if trailer.key.as_ref() == DEPENDS_ON_SPEC.key {
+                    self.add_dependency(issue, other);
+                } else if trailer.key.as_ref() == BLOCKS_SPEC.key {
+                    self.add_dependency(other, issue);
+                }
+            }
+        }
+    }
+
+    /// Add a single dependency
+    ///
+    pub fn add_dependency(&mut self, issue: Oid, dependency: Oid) {
+        self.dependencies
+            .entry(issue)
+            .or_default()
+            .insert(dependency);
+    }
+
+    /// Get the issues an issue depends on directly
+    ///
+    pub fn dependencies(&self, issue: Oid) -> Vec<Oid> {
+        self.dependencies
+            .get(&issue)
+            .map(|deps| deps.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Get the issues depending directly on an issue
+    ///
+    pub fn dependents(&self, issue: Oid) -> Vec<Oid> {
+        let mut retval: Vec<Oid> = self
+            .dependencies
+            .iter()
+            .filter(|&(_, deps)| deps.contains(&issue))
+            .map(|(id, _)| *id)
+            .collect();
+        retval.sort();
+        retval
+    }
+
+    /// Get all dependencies as pairs of a dependent issue and a dependency
+    ///
+    /// The pairs are returned in ascending order.
+    ///
+    pub fn edges(&self) -> Vec<(Oid, Oid)> {
+        let mut retval: Vec<(Oid, Oid)> = self
+            .dependencies
+            .iter()
+            .flat_map(|(issue, deps)| deps.iter().map(move |dep| (*issue, *dep)))
+            .collect();
+        retval.sort();
+        retval
+    }
+
+    /// Check whether an issue is ready
+    ///
+    /// An issue is considered ready if all the issues it depends on are
+    /// closed, as determined by the predicate supplied.
+    ///
+    pub fn is_ready<F>(&self, issue: Oid, is_closed: F) -> bool
+        where F: Fn(Oid) -> bool
+    {
+        self.dependencies(issue).into_iter().all(is_closed)
+    }
+
+    /// Find cycles in the dependency graph
+    ///
+    /// Each cycle is returned as a sequence of issues, each depending on the
+    /// next one and the last one depending on the first one. Every cycle is
+    /// reported only once.
+    ///
+    pub fn cycles(&self) -> Vec<Vec<Oid>> {
+        let mut retval = Vec::new();
+        let mut done = HashSet::new();
+
+        let mut roots: Vec<&Oid> = self.dependencies.keys().collect();
+        roots.sort();
+        for root in roots {
+            let mut path = Vec::new();
+            self.find_cycles(*root, &mut path, &mut done, &mut retval);
+        }
+        retval
+    }
+
+    /// Depth-first search for cycles, starting at an issue
+    ///
+    fn find_cycles(&self,
+                   issue: Oid,
+                   path: &mut Vec<Oid>,
+                   done: &mut HashSet<Oid>,
+                   cycles: &mut Vec<Vec<Oid>>)
+    {
+        if let Some(pos) = path.iter().position(|id| *id == issue) {
+            cycles.push(path[pos..].to_vec());
+            return;
+        }
+        if done.contains(&issue) {
+            return;
+        }
+
+        path.push(issue);
+        for dependency in self.dependencies(issue) {
+            self.find_cycles(dependency, path, done, cycles);
+        }
+        path.pop();
+        done.insert(issue);
+    }
+
+    /// Resolve the issue referenced by a trailer value
+    ///
+    /// The value may refer to any message of the issue. The issue is
+    /// identified by the id of its initial message.
+    ///
+    fn resolve(&self, value: &str) -> Option<Oid> {
+        value
+            .split_whitespace()
+            .next()
+            .and_then(|word| resolve_object_id(self.repo, word))
+            .and_then(|object| object.peel(git2::ObjectType::Commit).ok())
+            .and_then(|object| object.into_commit().ok())
+            .and_then(|message| self.repo.issue_with_message(&message).ok())
+            .map(|issue| issue.id())
+    }
+}
+
+
+/// Build the dependency graph of issues
+///
+/// For each issue, the relations are accumulated from the messages reachable
+/// from the head selected via the function supplied. Issues for which no head
+/// is selected are part of the graph, but without any relations. In addition
+/// to the graph, this function returns the set of issues with a status listed
+/// in `closed_statuses`.
+///
+pub fn dependency_graph<'r, I, F>(repo: &'r git2::Repository,
+                                  issues: I,
+                                  head: F,
+                                  closed_statuses: &[String]
+) -> Result<(DependencyGraph<'r>, HashSet<Oid>)>
+    where I: IntoIterator<Item = Issue<'r>>,
+          F: Fn(&Issue<'r>) -> Result<Option<Oid>>
+{
+    let mut graph = DependencyGraph::new(repo);
+    let mut closed = HashSet::new();
+    for issue in issues {
+        let messages = match head(&issue)? {
+            Some(head) => issue.messages_from(head)?.collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+
+        let acc: HashMap<_, _> = messages
+            .clone()
+            .into_iter()
+            .accumulate_trailers(&[ISSUE_STATUS_SPEC]);
+        let is_closed = acc
+            .get(ISSUE_STATUS_SPEC.key)
+            .and_then(|values| values.clone().into_iter().next())
+            .map(|status| closed_statuses.contains(&status.to_string()))
+            .unwrap_or(false);
+        if is_closed {
+            closed.insert(issue.id());
+        }
+
+        graph.add_issue(issue.id(), messages);
+    }
+
+    Ok((graph, closed))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    use repository::RepositoryExt;
+
+    // DependencyGraph tests
+
+    #[test]
+    fn dependency_graph() {
+        let mut testing_repo = TestingRepo::new("dependency_graph");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue2 = repo
+            .create_issue(&sig, &sig, format!("Test message 2\n\nDit-depends-on: {}", issue1.id()), &empty_tree, vec![])
+            .expect("Could not create issue");
+        let issue3 = repo
+            .create_issue(&sig, &sig, format!("Test message 3\n\nDit-blocks: {}", issue1.id()), &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let mut graph = DependencyGraph::new(repo);
+        for issue in &[&issue1, &issue2, &issue3] {
+            let initial = issue.initial_message().expect("Could not retrieve initial message");
+            graph.add_issue(issue.id(), vec![initial]);
+        }
+
+        assert_eq!(graph.dependencies(issue1.id()), vec![issue3.id()]);
+        assert_eq!(graph.dependencies(issue2.id()), vec![issue1.id()]);
+        assert_eq!(graph.dependents(issue1.id()), vec![issue2.id()]);
+        assert_eq!(graph.edges().len(), 2);
+        assert!(graph.edges().contains(&(issue1.id(), issue3.id())));
+        assert!(graph.cycles().is_empty());
+
+        assert!(graph.is_ready(issue3.id(), |_| false));
+        assert!(!graph.is_ready(issue1.id(), |_| false));
+        assert!(graph.is_ready(issue1.id(), |id| id == issue3.id()));
+
+        graph.add_dependency(issue3.id(), issue2.id());
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 1);
+        let mut cycle = cycles[0].clone();
+        cycle.sort();
+        let mut expected = vec![issue1.id(), issue2.id(), issue3.id()];
+        expected.sort();
+        assert_eq!(cycle, expected);
+    }
+
+    #[test]
+    fn dependency_normalization() {
+        let mut testing_repo = TestingRepo::new("dependency_normalization");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let reply = issue1
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        repo.reference("refs/heads/issue1", issue1.id(), false, "create branch")
+            .expect("Could not create branch");
+
+        // a reply, an abbreviated id and a branch name
+        let message = format!("Test message 3\n\nDit-depends-on: {}\nDit-blocks: {}\nDit-blocks: issue1",
+                              reply.id(),
+                              &issue1.id().to_string()[..10]);
+        let issue2 = repo
+            .create_issue(&sig, &sig, message, &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let mut graph = DependencyGraph::new(repo);
+        let initial = issue2.initial_message().expect("Could not retrieve initial message");
+        graph.add_issue(issue2.id(), vec![initial]);
+
+        assert_eq!(graph.edges(), {
+            let mut edges = vec![(issue2.id(), issue1.id()), (issue1.id(), issue2.id())];
+            edges.sort();
+            edges
+        });
+        assert!(!graph.dependencies(issue2.id()).contains(&reply.id()));
+    }
+
+    #[test]
+    fn dependency_graph_of_issues() {
+        let mut testing_repo = TestingRepo::new("dependency_graph_of_issues");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let closing = issue1
+            .add_message(&sig, &sig, "Test message 2\n\nDit-status: done", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue1.update_head(closing.id(), true).expect("Could not update head");
+        let issue2 = repo
+            .create_issue(&sig, &sig, format!("Test message 3\n\nDit-depends-on: {}", issue1.id()), &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let closed_statuses = vec!["done".to_owned()];
+        let (graph, closed) = super::dependency_graph(
+            repo,
+            repo.issues().expect("Could not retrieve issues"),
+            |issue: &Issue| Ok(Some(issue.local_head()?.target().expect("Could not get head target"))),
+            &closed_statuses,
+        ).expect("Could not build dependency graph");
+
+        assert_eq!(graph.dependencies(issue2.id()), vec![issue1.id()]);
+        assert!(graph.dependencies(issue1.id()).is_empty());
+        assert!(closed.contains(&issue1.id()));
+        assert!(!closed.contains(&issue2.id()));
+        assert!(graph.is_ready(issue2.id(), |id| closed.contains(&id)));
+    }
+}
@@ -0,0 +1,286 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Draft messages
+//!
+//! Messages which are not yet ready for publication may be stored as drafts.
+//! A draft is a commit referenced by `refs/dit-drafts/<issue>/<n>`, where
+//! `<issue>` is the id of the issue the message will be added to or `new` for
+//! drafts of new issues. The commit carries the tree and parents of the
+//! message to create as well as the raw text, which may still contain comments
+//! and is only checked upon publication. Drafts are not messages: they are
+//! never referred to by dit references and are hence never pushed.
+//!
+
+use git2::{self, Commit, Oid};
+use std::str::FromStr;
+
+use message::LineIteratorExt;
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Prefix of draft references
+///
+pub const DRAFT_REF_PREFIX: &str = "refs/dit-drafts/";
+
+/// Placeholder used instead of an issue id for drafts of new issues
+///
+const NEW_ISSUE: &str = "new";
+
+
+/// Draft of a message
+///
+pub struct Draft<'r> {
+    repo: &'r git2::Repository,
+    name: String,
+    commit: Commit<'r>,
+}
+
+impl<'r> Draft<'r> {
+    /// Save a new draft
+    ///
+    /// The draft will be added to the issue with the id supplied upon
+    /// publication or, if no id is supplied, published as a new issue.
+    ///
+    pub fn save<'a, I>(repo: &'r git2::Repository,
+                       issue: Option<Oid>,
+                       author: &git2::Signature,
+                       committer: &git2::Signature,
+                       message: &str,
+                       tree: &git2::Tree,
+                       parents: I
+    ) -> Result<Self>
+        where I: IntoIterator<Item = &'a Commit<'a>>
+    {
+        let prefix = issue
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| NEW_ISSUE.to_owned());
+
+        // Drafts of an issue are numbered consecutively.
+        let number = Self::of_prefix(repo, &prefix)?
+            .iter()
+            .filter_map(|draft| draft.number())
+            .max()
+            .unwrap_or(0) + 1;
+        let name = format!("{}/{}", prefix, number);
+
+        let parents: Vec<&Commit> = parents.into_iter().collect();
+        let commit = repo
+            .commit(None, author, committer, message, tree, &parents)
+            .and_then(|id| repo.find_commit(id))
+            .chain_err(|| EK::CannotCreateMessage)?;
+        repo.reference(&format!("{}{}", DRAFT_REF_PREFIX, name), commit.id(), false, "save draft")
+            .chain_err(|| EK::CannotSetReference(name.clone()))?;
+
+        Ok(Draft { repo, name, commit })
+    }
+
+    /// Find a draft by its name, e.g. `<issue>/<n>`
+    ///
+    pub fn find(repo: &'r git2::Repository, name: &str) -> Result<Self> {
+        let commit = repo
+            .find_reference(&format!("{}{}", DRAFT_REF_PREFIX, name))
+            .and_then(|reference| reference.peel(git2::ObjectType::Commit))
+            .chain_err(|| EK::CannotFindDraft(name.to_owned()))?
+            .into_commit()
+            .map_err(|_| Error::from_kind(EK::CannotFindDraft(name.to_owned())))?;
+
+        Ok(Draft { repo, name: name.to_owned(), commit })
+    }
+
+    /// Get all drafts with a specific issue part
+    ///
+    fn of_prefix(repo: &'r git2::Repository, prefix: &str) -> Result<Vec<Self>> {
+        let glob = format!("{}{}/*", DRAFT_REF_PREFIX, prefix);
+        let refs = repo
+            .references_glob(&glob)
+            .chain_err(|| EK::CannotGetReferences(glob.clone()))?;
+
+        let mut retval = Vec::new();
+        for item in refs {
+            let reference = item.chain_err(|| EK::CannotGetReference)?;
+            let name = reference
+                .name()
+                .unwrap_or_default()
+                .trim_start_matches(DRAFT_REF_PREFIX)
+                .to_owned();
+            let commit = reference
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .into_commit()
+                .map_err(|_| Error::from_kind(EK::CannotGetCommit))?;
+            retval.push(Draft { repo, name, commit });
+        }
+        Ok(retval)
+    }
+
+    /// Get the draft's name, e.g. `<issue>/<n>`
+    ///
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// Get the id of the issue the draft will be added to
+    ///
+    /// If the draft is the draft of a new issue, this function returns `None`.
+    ///
+    pub fn issue_id(&self) -> Option<Oid> {
+        self.name
+            .split('/')
+            .next()
+            .and_then(|part| Oid::from_str(part).ok())
+    }
+
+    /// Get the number of the draft
+    ///
+    fn number(&self) -> Option<usize> {
+        self.name
+            .rsplit('/')
+            .next()
+            .and_then(|part| usize::from_str(part).ok())
+    }
+
+    /// Get the commit holding the draft
+    ///
+    pub fn commit(&self) -> &Commit<'r> {
+        &self.commit
+    }
+
+    /// Get the raw text of the draft
+    ///
+    pub fn text(&self) -> &str {
+        self.commit.message_raw().unwrap_or_default()
+    }
+
+    /// Replace the text of the draft
+    ///
+    pub fn update(&mut self, message: &str) -> Result<()> {
+        let id = self
+            .commit
+            .amend(None, None, None, None, Some(message), None)
+            .chain_err(|| EK::CannotCreateMessage)?;
+        self.repo
+            .reference(&format!("{}{}", DRAFT_REF_PREFIX, self.name), id, true, "update draft")
+            .chain_err(|| EK::CannotSetReference(self.name.clone()))?;
+        self.commit = self.repo.find_commit(id).chain_err(|| EK::CannotGetCommit)?;
+        Ok(())
+    }
+
+    /// Get the message to publish
+    ///
+    /// This function strips comments from the draft's text and checks the
+    /// message's format. The message is returned if the text forms a valid
+    /// message.
+    ///
+    pub fn message(&self) -> Result<String> {
+        let lines: Vec<String> = self.text().lines().stripped().collect();
+        lines.iter().check_message_format()?;
+        Ok(lines.into_iter().collect_string())
+    }
+
+    /// Publish the draft
+    ///
+    /// If the draft's text forms a valid message, as returned by `message()`,
+    /// the message is created, either as a new issue or as a message of an
+    /// existing issue, and the draft is discarded. The id of the new message is
+    /// returned.
+    ///
+    pub fn publish(self, committer: &git2::Signature) -> Result<Oid> {
+        let message = self.message()?;
+
+        let id = {
+            let tree = self.commit.tree().chain_err(|| EK::CannotGetCommit)?;
+            let parents: Vec<Commit> = self.commit.parents().collect();
+            let author = self.commit.author();
+            match self.issue_id() {
+                Some(issue) => self.repo
+                    .find_issue(issue)?
+                    .add_message(&author, committer, message.trim(), &tree, parents.iter())?
+                    .id(),
+                None => self.repo
+                    .create_issue(&author, committer, message.trim(), &tree, parents.iter())?
+                    .id(),
+            }
+        };
+
+        self.discard()?;
+        Ok(id)
+    }
+
+    /// Discard the draft
+    ///
+    pub fn discard(self) -> Result<()> {
+        let refname = format!("{}{}", DRAFT_REF_PREFIX, self.name);
+        self.repo
+            .find_reference(&refname)
+            .and_then(|mut reference| reference.delete())
+            .chain_err(|| EK::CannotDeleteReference(refname))
+    }
+}
+
+
+/// Get all drafts in a repository
+///
+pub fn drafts(repo: &git2::Repository) -> Result<Vec<Draft<'_>>> {
+    Draft::of_prefix(repo, "*")
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // Draft tests
+
+    #[test]
+    fn publish_drafts() {
+        let mut testing_repo = TestingRepo::new("publish_drafts");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let draft = Draft::save(repo, None, &sig, &sig, "Test message 1\n\n# comment", &empty_tree, vec![])
+            .expect("Could not save draft");
+        assert_eq!(draft.name(), "new/1");
+        assert_eq!(draft.issue_id(), None);
+
+        let id = draft.publish(&sig).expect("Could not publish draft");
+        let issue = repo.find_issue(id).expect("Could not find issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        assert_eq!(initial_message.message(), Some("Test message 1"));
+        assert!(drafts(repo).expect("Could not get drafts").is_empty());
+
+        Draft::save(repo, Some(id), &sig, &sig, "", &empty_tree, vec![&initial_message])
+            .expect("Could not save draft");
+        let mut draft = Draft::save(repo, Some(id), &sig, &sig, "", &empty_tree, vec![&initial_message])
+            .expect("Could not save draft");
+        assert_eq!(draft.name(), format!("{}/2", id));
+        assert_eq!(drafts(repo).expect("Could not get drafts").len(), 2);
+
+        draft.update("Test message 2").expect("Could not update draft");
+        let message = draft.publish(&sig).expect("Could not publish draft");
+        let message = repo.find_commit(message).expect("Could not find message");
+        assert_eq!(message.parent_ids().collect::<Vec<_>>(), vec![id]);
+
+        let draft = Draft::find(repo, &format!("{}/1", id)).expect("Could not find draft");
+        assert!(draft.publish(&sig).is_err());
+    }
+}
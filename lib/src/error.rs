@@ -55,11 +55,26 @@ error_chain! {
             display("Cannot delete the reference '{}'", reference)
         }
 
+        CannotExpireReflog(reference: String) {
+            description("Cannot expire the reflog of a specific reference")
+            display("Cannot expire the reflog of '{}'", reference)
+        }
+
+        CannotGetRemotes {
+            description("Cannot get remotes from repository")
+            display("Cannot get the configured remotes from repository")
+        }
+
         CannotBuildTree {
             description("Cannot build Tree")
             display("Cannot build Tree")
         }
 
+        CannotFindDraft(name: String) {
+            description("Cannot find draft")
+            display("Cannot find draft {}", name)
+        }
+
         CannotFindIssueHead(id: Oid) {
             description("Cannot find issue HEAD")
             display("Cannot find issue HEAD for {}", id)
@@ -90,6 +105,11 @@ error_chain! {
             display("Malformed trailer: {}", trailer)
         }
 
+        MalformedIssueReference(reference: String) {
+            description("Found malformed issue reference")
+            display("Malformed issue reference: {}", reference)
+        }
+
         EmptyMessage {
             description("An empty message was supplied")
             display("The message is empty")
@@ -104,5 +124,15 @@ error_chain! {
             description("The message supplied is malformed")
             display("The message supplied is malformed")
         }
+
+        MissingTrailer(key: String) {
+            description("A required trailer is missing")
+            display("Missing required trailer: {}", key)
+        }
+
+        MalformedPluginVariable(name: String) {
+            description("Found malformed plugin environment variable")
+            display("Malformed plugin environment variable: {}", name)
+        }
     }
 }
@@ -0,0 +1,351 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Fix tracking utilities
+//!
+//! Messages may refer to arbitrary commits via their non-first parents or via
+//! `Fixes` trailers. If those commits are not messages themselves, they are
+//! considered to be commits fixing the issue. This module provides utilities
+//! for retrieving those "fix commits" and for finding revisions which contain
+//! them.
+//!
+
+use git2::{self, Oid, Reference};
+use std::collections::HashSet;
+
+use issue::Issue;
+use message::Message;
+use repository::RepositoryExt;
+use trailer::spec::FIXES_SPEC;
+use utils::resolve_object_id;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Set of commits fixing an issue
+///
+pub type FixCommits = HashSet<Oid>;
+
+
+/// Type for tracking fixes of issues
+///
+/// Computing which commits are referenced as fixes requires knowledge about
+/// all the messages in a repository. Use this type in order to compute that
+/// information only once for multiple queries.
+///
+pub struct FixTracker<'r> {
+    repo: &'r git2::Repository,
+    /// Ids of all known messages
+    messages: HashSet<Oid>,
+}
+
+impl<'r> FixTracker<'r> {
+    /// Create a new FixTracker
+    ///
+    /// The messages of all issues known to the repository are gathered during
+    /// the construction.
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Result<Self> {
+        let mut messages = HashSet::new();
+        for issue in repo.issues()? {
+            for message in issue.messages()? {
+                messages.insert(message?.id());
+            }
+        }
+
+        Ok(FixTracker { repo, messages })
+    }
+
+    /// Get the commits fixing an issue
+    ///
+    /// The commits returned are those referenced by the issue's messages via
+    /// non-first parents or `Fixes` trailers, excluding references to other
+    /// messages. The parents of the initial message are not considered, since
+    /// they usually refer to the commit in which a bug was observed or on which
+    /// a patch is based.
+    ///
+    /// Only the first word of a `Fixes` trailer's value is considered, which
+    /// allows for the common `Fixes: <id> ("<subject>")` form. Values which
+    /// are not the (abbreviated) id of a commit, e.g. branch names, are
+    /// ignored.
+    ///
+    pub fn fix_commits(&self, issue: &Issue<'r>) -> Result<FixCommits> {
+        let mut retval = HashSet::new();
+        for item in issue.messages()? {
+            let message = item?;
+
+            let referenced = message
+                .trailers()
+                .filter(|trailer| trailer.key.as_ref() == FIXES_SPEC.key)
+                .filter_map(|trailer| self.resolve(&trailer.value.to_string()));
+            retval.extend(referenced.filter(|id| !self.messages.contains(id)));
+
+            if message.id() == issue.id() {
+                continue;
+            }
+
+            retval.extend(message
+                .parent_ids()
+                .skip(1)
+                .filter(|parent| !self.messages.contains(parent))
+            );
+        }
+        Ok(retval)
+    }
+
+    /// Check whether fixes became part of a range of revisions
+    ///
+    /// This function returns `true` if all of the fix commits are contained
+    /// in `to` but not all of them are contained in `from`. If no `from`
+    /// revision is provided, the function is equivalent to `contains_fixes()`.
+    ///
+    pub fn fixed_in_range(&self, from: Option<Oid>, to: Oid, fixes: &FixCommits) -> Result<bool> {
+        if !self.contains_fixes(to, fixes)? {
+            return Ok(false);
+        }
+
+        match from {
+            Some(from) => self.contains_fixes(from, fixes).map(|contained| !contained),
+            None => Ok(true),
+        }
+    }
+
+    /// Check whether a revision contains a fix commit
+    ///
+    pub fn contains_fix(&self, rev: Oid, fix: Oid) -> Result<bool> {
+        if rev == fix {
+            return Ok(true);
+        }
+        self.repo
+            .graph_descendant_of(rev, fix)
+            .chain_err(|| EK::CannotGetCommitForRev(fix.to_string()))
+    }
+
+    /// Check whether a revision contains all the fix commits provided
+    ///
+    /// If no fix commits are provided, this function returns `false`, since
+    /// an issue without any fix cannot be considered fixed.
+    ///
+    pub fn contains_fixes(&self, rev: Oid, fixes: &FixCommits) -> Result<bool> {
+        if fixes.is_empty() {
+            return Ok(false);
+        }
+
+        for fix in fixes {
+            if !self.contains_fix(rev, *fix)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Get the references containing all the fix commits provided
+    ///
+    /// Only references matching the glob provided and referring to commits
+    /// are considered. References within the dit namespaces are never
+    /// returned.
+    ///
+    pub fn refs_containing(&self, glob: &str, fixes: &FixCommits) -> Result<Vec<Reference<'r>>> {
+        use issue::IssueRefType;
+
+        let mut retval = Vec::new();
+        let refs = self
+            .repo
+            .references_glob(glob)
+            .chain_err(|| EK::CannotGetReferences(glob.to_owned()))?;
+        for item in refs {
+            let reference = item.chain_err(|| EK::CannotGetReference)?;
+            if IssueRefType::of_ref(reference.name().unwrap_or_default()).is_some() {
+                continue;
+            }
+
+            let target = match reference.peel(git2::ObjectType::Commit) {
+                Ok(commit) => commit.id(),
+                Err(_) => continue, // Refs to trees or blobs don't contain fixes.
+            };
+            if self.contains_fixes(target, fixes)? {
+                retval.push(reference);
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Resolve the commit referenced by a trailer value to a commit id
+    ///
+    /// Only object ids are accepted, symbolic names are ignored.
+    ///
+    fn resolve(&self, value: &str) -> Option<Oid> {
+        value
+            .split_whitespace()
+            .next()
+            .and_then(|word| resolve_object_id(self.repo, word))
+            .and_then(|object| object.peel(git2::ObjectType::Commit).ok())
+            .map(|commit| commit.id())
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // FixTracker tests
+
+    #[test]
+    fn fix_commits() {
+        let mut testing_repo = TestingRepo::new("fix_commits");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let empty_parents: Vec<&git2::Commit> = vec![];
+
+        let base = repo
+            .commit(None, &sig, &sig, "Base", &empty_tree, &empty_parents)
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let fix = repo
+            .commit(None, &sig, &sig, "Fix", &empty_tree, &[&base])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        repo.reference("refs/heads/old", base.id(), false, "create old branch")
+            .expect("Could not create reference");
+        repo.reference("refs/heads/new", fix.id(), false, "create new branch")
+            .expect("Could not create reference");
+
+        let other_issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let other_initial = other_issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 2", &empty_tree, vec![&base])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message, &other_initial])
+            .expect("Could not add message");
+        issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&initial_message, &fix])
+            .expect("Could not add message");
+
+        let tracker = FixTracker::new(repo).expect("Could not create fix tracker");
+
+        let fixes = tracker.fix_commits(&issue).expect("Could not retrieve fixes");
+        assert_eq!(fixes.len(), 1);
+        assert!(fixes.contains(&fix.id()));
+
+        let refs: Vec<_> = tracker
+            .refs_containing("refs/heads/*", &fixes)
+            .expect("Could not retrieve references")
+            .into_iter()
+            .map(|r| r.name().expect("Could not retrieve name").to_string())
+            .collect();
+        assert_eq!(refs, vec!["refs/heads/new".to_string()]);
+
+        let other_fixes = tracker
+            .fix_commits(&other_issue)
+            .expect("Could not retrieve fixes");
+        assert!(other_fixes.is_empty());
+        assert!(!tracker.contains_fixes(fix.id(), &other_fixes).expect("Could not check fixes"));
+    }
+
+    #[test]
+    fn fixes_trailer() {
+        let mut testing_repo = TestingRepo::new("fixes_trailer");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let empty_parents: Vec<&git2::Commit> = vec![];
+
+        let base = repo
+            .commit(None, &sig, &sig, "Base", &empty_tree, &empty_parents)
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let fix = repo
+            .commit(None, &sig, &sig, "Fix", &empty_tree, &[&base])
+            .expect("Could not create commit");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = format!("Test message 2\n\nFixes: {} (\"Fix\")\nFixes: nonexistent", fix);
+        issue
+            .add_message(&sig, &sig, message, &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let tracker = FixTracker::new(repo).expect("Could not create fix tracker");
+        let fixes = tracker.fix_commits(&issue).expect("Could not retrieve fixes");
+        assert_eq!(fixes.len(), 1);
+        assert!(fixes.contains(&fix));
+
+        assert!(tracker.fixed_in_range(Some(base.id()), fix, &fixes).expect("Could not check range"));
+        assert!(!tracker.fixed_in_range(Some(fix), fix, &fixes).expect("Could not check range"));
+        assert!(!tracker.fixed_in_range(None, base.id(), &fixes).expect("Could not check range"));
+    }
+
+    #[test]
+    fn symbolic_fixes_ignored() {
+        let mut testing_repo = TestingRepo::new("symbolic_fixes_ignored");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let empty_parents: Vec<&git2::Commit> = vec![];
+
+        let base = repo
+            .commit(None, &sig, &sig, "Base", &empty_tree, &empty_parents)
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let fix = repo
+            .commit(None, &sig, &sig, "Fix", &empty_tree, &[&base])
+            .expect("Could not create commit");
+        repo.reference("refs/heads/fix", fix, false, "create fix branch")
+            .expect("Could not create branch");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = format!("Test message 2\n\nFixes: fix\nFixes: fix~1\nFixes: {}", &fix.to_string()[..10]);
+        issue
+            .add_message(&sig, &sig, message, &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        // only the abbreviated id is resolved
+        let tracker = FixTracker::new(repo).expect("Could not create fix tracker");
+        let fixes = tracker.fix_commits(&issue).expect("Could not retrieve fixes");
+        assert_eq!(fixes.len(), 1);
+        assert!(fixes.contains(&fix));
+        assert!(!fixes.contains(&base.id()));
+    }
+}
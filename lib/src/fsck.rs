@@ -0,0 +1,346 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Integrity checking utilities
+//!
+//! This module provides utilities for checking the consistency of dit
+//! references and messages, e.g. for detecting references which do not belong
+//! to the issue they claim to belong to.
+//!
+
+use git2::{self, Oid};
+use std::collections::HashSet;
+use std::fmt;
+use std::result::Result as RResult;
+
+use issue::{Issue, IssueRefType};
+use message::{LineIteratorExt, Message};
+use message::block::Block;
+use repository::RepositoryExt;
+use trailer::spec::ISSUE_REF_SPEC;
+use xref::{IssueRef, IssueResolver};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Representation of an integrity problem
+///
+pub enum Problem {
+    /// A reference in a dit namespace which is neither a head nor a leaf
+    UnknownReference(String),
+    /// A head reference referring to a message not belonging to its issue
+    ///
+    /// If the message belongs to another issue, that issue's id is included.
+    ///
+    RogueHead(String, Option<Oid>),
+    /// A leaf reference referring to a message not belonging to its issue
+    ///
+    /// If the message belongs to another issue, that issue's id is included.
+    ///
+    ForeignLeaf(String, Option<Oid>),
+    /// A local leaf reference which is not required
+    CollectableLeaf(String),
+    /// A message which is not formatted properly
+    MalformedMessage(Oid, Error),
+    /// A line in a message resembling a dit trailer which is not recognized
+    MalformedTrailer(Oid, String),
+    /// A reference to an issue in another repository which cannot be resolved
+    DanglingIssueReference(Oid, String),
+}
+
+impl Problem {
+    /// Check whether the problem may be fixed automatically
+    ///
+    /// Only problems which can be fixed without losing any information are
+    /// considered fixable.
+    ///
+    pub fn is_fixable(&self) -> bool {
+        is_match!(*self, Problem::CollectableLeaf(_))
+    }
+
+    /// Fix the problem
+    ///
+    /// Calling this function for a problem which is not fixable is a no-op.
+    ///
+    pub fn fix(&self, repo: &git2::Repository) -> Result<()> {
+        match *self {
+            Problem::CollectableLeaf(ref name) => repo
+                .find_reference(name)
+                .and_then(|mut reference| reference.delete())
+                .chain_err(|| EK::CannotDeleteReference(name.clone())),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        match *self {
+            Problem::UnknownReference(ref name) =>
+                write!(f, "unknown reference: {}", name),
+            Problem::RogueHead(ref name, Some(ref other)) =>
+                write!(f, "rogue head: {} refers to a message of {}", name, other),
+            Problem::RogueHead(ref name, None) =>
+                write!(f, "rogue head: {} does not refer to a message of its issue", name),
+            Problem::ForeignLeaf(ref name, Some(ref other)) =>
+                write!(f, "foreign leaf: {} refers to a message of {}", name, other),
+            Problem::ForeignLeaf(ref name, None) =>
+                write!(f, "foreign leaf: {} does not refer to a message of its issue", name),
+            Problem::CollectableLeaf(ref name) =>
+                write!(f, "collectable leaf: {}", name),
+            Problem::MalformedMessage(ref id, ref err) =>
+                write!(f, "malformed message: {} ({})", id, err),
+            Problem::MalformedTrailer(ref id, ref line) =>
+                write!(f, "malformed trailer: {} ({})", id, line),
+            Problem::DanglingIssueReference(ref id, ref reference) =>
+                write!(f, "dangling issue reference: {} ({})", id, reference),
+        }
+    }
+}
+
+
+/// Integrity checker
+///
+/// Use this type for checking the integrity of the dit references and
+/// messages in a repository.
+///
+pub struct IntegrityCheck<'r> {
+    repo: &'r git2::Repository,
+    resolver: IssueResolver<'r>,
+}
+
+impl<'r> IntegrityCheck<'r> {
+    /// Create a new integrity checker
+    ///
+    /// References to issues in other repositories are resolved only via the
+    /// repository's remotes.
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Self {
+        IntegrityCheck { repo, resolver: IssueResolver::new(repo) }
+    }
+
+    /// Use a specific resolver for references to issues in other repositories
+    ///
+    pub fn resolver(mut self, resolver: IssueResolver<'r>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Check all dit references and issues
+    ///
+    /// This function checks all references in the local and the remote dit
+    /// namespaces as well as all the issues known to the repository.
+    ///
+    pub fn check_all(&self) -> Result<Vec<Problem>> {
+        let mut retval = self.check_refs("refs/dit/**")?;
+        retval.append(&mut self.check_refs("refs/remotes/*/dit/**")?);
+
+        // Messages may be reachable from multiple issues, e.g. through foreign
+        // leaves. Make sure we report problems with those messages only once.
+        let mut checked = HashSet::new();
+        for issue in self.repo.issues()? {
+            retval.append(&mut self.check_leaves(&issue)?);
+            retval.append(&mut self.check_messages(&issue, &mut checked)?);
+        }
+        Ok(retval)
+    }
+
+    /// Check references matching a glob
+    ///
+    /// This function reports references which are not recognized as dit
+    /// references, head references referring to messages of other issues and
+    /// leaves referring to messages of other issues.
+    ///
+    pub fn check_refs(&self, glob: &str) -> Result<Vec<Problem>> {
+        let mut retval = Vec::new();
+        let refs = self
+            .repo
+            .references_glob(glob)
+            .chain_err(|| EK::CannotGetReferences(glob.to_owned()))?;
+        for item in refs {
+            let reference = item.chain_err(|| EK::CannotGetReference)?;
+            let name = reference
+                .name()
+                .ok_or_else(|| Error::from_kind(EK::ReferenceNameError))?
+                .to_owned();
+
+            let (id, ref_type) = match IssueRefType::of_ref(&name) {
+                Some(data) => data,
+                None => {
+                    retval.push(Problem::UnknownReference(name));
+                    continue;
+                },
+            };
+
+            // Find out to which issue the referred message actually belongs.
+            let actual = reference
+                .peel(git2::ObjectType::Commit)
+                .ok()
+                .and_then(|object| object.into_commit().ok())
+                .and_then(|message| self.repo.issue_with_message(&message).ok())
+                .map(|issue| issue.id());
+            if actual == Some(id) {
+                continue;
+            }
+
+            retval.push(match ref_type {
+                IssueRefType::Head => Problem::RogueHead(name, actual),
+                _ => Problem::ForeignLeaf(name, actual),
+            });
+        }
+        Ok(retval)
+    }
+
+    /// Check an issue's messages and local references
+    ///
+    /// This function reports collectable local leaves as well as messages
+    /// which are malformed, contain malformed trailers or refer to issues in
+    /// other repositories which cannot be resolved.
+    ///
+    pub fn check_issue(&self, issue: &Issue<'r>) -> Result<Vec<Problem>> {
+        let mut retval = self.check_leaves(issue)?;
+        retval.append(&mut self.check_messages(issue, &mut HashSet::new())?);
+        Ok(retval)
+    }
+
+    /// Check an issue's local leaves
+    ///
+    fn check_leaves(&self, issue: &Issue<'r>) -> Result<Vec<Problem>> {
+        let mut retval = Vec::new();
+        for item in self.repo.collectable_refs().for_issue(issue)? {
+            let reference = item?;
+            if IssueRefType::of_ref(reference.name().unwrap_or_default())
+                .map(|data| data.1 == IssueRefType::Leaf)
+                .unwrap_or(false)
+            {
+                retval.push(Problem::CollectableLeaf(reference
+                    .name()
+                    .unwrap_or_default()
+                    .to_owned()
+                ));
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Check the messages of an issue not already checked
+    ///
+    fn check_messages(&self, issue: &Issue<'r>, checked: &mut HashSet<Oid>) -> Result<Vec<Problem>> {
+        let mut retval = Vec::new();
+        for item in issue.messages()? {
+            let message = item?;
+            if !checked.insert(message.id()) {
+                continue;
+            }
+
+            if let Err(err) = message.message_lines().check_message_format() {
+                retval.push(Problem::MalformedMessage(message.id(), err));
+            }
+
+            for block in message.body_blocks() {
+                if let Block::Text(lines) = block {
+                    retval.extend(lines
+                        .into_iter()
+                        .filter(|line| line.starts_with("Dit-"))
+                        .map(|line| Problem::MalformedTrailer(message.id(), line))
+                    );
+                }
+            }
+
+            for trailer in message.trailers() {
+                if trailer.key.as_ref() != ISSUE_REF_SPEC.key {
+                    continue;
+                }
+                let value = trailer.value.to_string();
+                match value.parse::<IssueRef>() {
+                    Ok(reference) => if self.resolver.resolve(&reference)?.is_none() {
+                        retval.push(Problem::DanglingIssueReference(message.id(), value));
+                    },
+                    Err(_) => retval.push(Problem::MalformedTrailer(message.id(), trailer.to_string())),
+                }
+            }
+        }
+
+        Ok(retval)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // IntegrityCheck tests
+
+    #[test]
+    fn integrity_check() {
+        let mut testing_repo = TestingRepo::new("integrity_check");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue1 = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message1 = issue1
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue1
+            .add_message(&sig, &sig, "Test message 2\nFoo", &empty_tree, vec![&initial_message1])
+            .expect("Could not add message");
+        let message2 = issue1
+            .add_message(&sig, &sig, "Test message 3\n\nDit-status closed\nfoo", &empty_tree, vec![&message1])
+            .expect("Could not add message");
+        let message3 = issue1
+            .add_message(&sig, &sig, format!("Test message 5\n\nDit-ref: origin#{}", issue1.id()), &empty_tree, vec![&message2])
+            .expect("Could not add message");
+
+        let issue2 = repo
+            .create_issue(&sig, &sig, "Test message 4", &empty_tree, vec![])
+            .expect("Could not create issue");
+        issue2.add_leaf(message2.id()).expect("Could not add leaf");
+        repo.reference(
+            &format!("refs/dit/{}/foo", issue2.id()),
+            issue2.id(),
+            false,
+            "create unknown reference"
+        ).expect("Could not create reference");
+
+        let problems: Vec<String> = IntegrityCheck::new(repo)
+            .check_all()
+            .expect("Could not check integrity")
+            .into_iter()
+            .map(|problem| problem.to_string())
+            .collect();
+
+        let leaf1 = format!("refs/dit/{}/leaves/{}", issue1.id(), message1.id());
+        let leaf2 = format!("refs/dit/{}/leaves/{}", issue2.id(), message2.id());
+        let expected = [
+            format!("foreign leaf: {} refers to a message of {}", leaf2, issue1.id()),
+            format!("unknown reference: refs/dit/{}/foo", issue2.id()),
+            format!("collectable leaf: {}", leaf1),
+            format!("collectable leaf: refs/dit/{}/leaves/{}", issue1.id(), message2.id()),
+            format!("malformed message: {} (The message supplied is malformed)", message1.id()),
+            format!("malformed trailer: {} (Dit-status closed)", message2.id()),
+            format!("dangling issue reference: {} (origin#{})", message3.id(), issue1.id()),
+        ];
+        for problem in expected.iter() {
+            assert!(problems.contains(problem), "Missing problem: {}", problem);
+        }
+        assert_eq!(problems.len(), expected.len());
+    }
+}
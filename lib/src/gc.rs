@@ -17,6 +17,7 @@ use std::borrow::Borrow;
 
 use issue::{Issue, IssueRefType};
 use iter::{self, RefsReferringTo};
+use remote::remote_of_ref;
 use utils::ResultIterExt;
 
 use error::*;
@@ -34,9 +35,21 @@ pub type ReferenceCollector<'r> = iter::ReferenceDeletingIter<
 >;
 
 
+/// Specification of the conditions under which local heads are collected
+///
 pub enum ReferenceCollectionSpec {
+    /// Never collect local heads
     Never,
+    /// Collect local heads backed by any remote head
     BackedByRemoteHead,
+    /// Collect local heads backed by the head of a specific remote
+    BackedByRemote(String),
+    /// Collect local heads equal to the selected remote head
+    ///
+    /// The remote head is selected according to the remote priorities
+    /// configured via `CollectableRefs::remote_priorities()`.
+    ///
+    Always,
 }
 
 
@@ -52,6 +65,8 @@ pub struct CollectableRefs<'r>
     consider_remote_refs: bool,
     /// Under what circumstances should local heads be collected?
     collect_heads: ReferenceCollectionSpec,
+    /// Names of remotes, in descending order of priority
+    remote_priorities: Vec<String>,
 }
 
 impl<'r> CollectableRefs<'r>
@@ -67,6 +82,7 @@ impl<'r> CollectableRefs<'r>
             repo: repo,
             consider_remote_refs: false,
             collect_heads: ReferenceCollectionSpec::Never,
+            remote_priorities: vec!["*".to_owned()],
         }
     }
 
@@ -91,6 +107,20 @@ impl<'r> CollectableRefs<'r>
         self
     }
 
+    /// Set the priorities of remotes
+    ///
+    /// The priorities are used for selecting a remote head, e.g. for the
+    /// `Always` collection spec. The names are expected in descending order of
+    /// priority. The special name `*` matches any remote. Remotes not matched
+    /// are not considered. By default, any remote is accepted.
+    ///
+    pub fn remote_priorities<I>(mut self, names: I) -> Self
+        where I: IntoIterator<Item = String>
+    {
+        self.remote_priorities = names.into_iter().collect();
+        self
+    }
+
     /// Find collectable references for an issue
     ///
     /// Construct an iterator yielding all collectable references for a given
@@ -137,6 +167,31 @@ impl<'r> CollectableRefs<'r>
                         )?;
                     }
                 },
+                ReferenceCollectionSpec::BackedByRemote(ref remote) => {
+                    let refname = format!("refs/remotes/{}/dit/{}/head", remote, issue.ref_part());
+                    if let Ok(remote_head) = self.repo.find_reference(&refname) {
+                        head_history.push(
+                            remote_head
+                                .peel(git2::ObjectType::Commit)
+                                .chain_err(|| EK::CannotGetCommit)?
+                                .id()
+                        )?;
+                    }
+                },
+                ReferenceCollectionSpec::Always => {
+                    // We only push the selected remote head if it refers to the
+                    // very message the local head refers to, since the local
+                    // head would be reported for any descendant.
+                    let local_id = local_head
+                        .peel(git2::ObjectType::Commit)
+                        .chain_err(|| EK::CannotGetCommit)?
+                        .id();
+                    if let Some(remote_id) = self.selected_remote_head(issue)? {
+                        if remote_id == local_id {
+                            head_history.push(remote_id)?;
+                        }
+                    }
+                },
             };
             let mut referring_refs = iter::RefsReferringTo::new(head_history);
             referring_refs.watch_ref(local_head)?;
@@ -167,6 +222,66 @@ impl<'r> CollectableRefs<'r>
         Ok(retval)
     }
 
+    /// Find remote references of remotes which no longer exist
+    ///
+    /// This function returns all remote-tracking dit references associated
+    /// with remotes which are not configured for the repository.
+    ///
+    pub fn stale_remote_refs(&self) -> Result<Vec<Reference<'r>>> {
+        let remotes = self
+            .repo
+            .remotes()
+            .chain_err(|| EK::CannotGetRemotes)?;
+        let remotes: Vec<&str> = remotes.iter().flatten().collect();
+
+        let glob = "refs/remotes/*/dit/**";
+        let mut retval = Vec::new();
+        for item in self.repo.references_glob(glob).chain_err(|| EK::CannotGetReferences(glob.to_owned()))? {
+            let reference = item.chain_err(|| EK::CannotGetReference)?;
+            let is_stale = reference
+                .name()
+                .and_then(remote_of_ref)
+                .map(|remote| !remotes.contains(&remote))
+                .unwrap_or(false);
+            if is_stale {
+                retval.push(reference);
+            }
+        }
+        Ok(retval)
+    }
+
+    /// Get the message referred to by the selected remote head of an issue
+    ///
+    fn selected_remote_head(&self, issue: &Issue<'r>) -> Result<Option<git2::Oid>> {
+        let mut selected = None;
+        for item in issue.remote_refs(IssueRefType::Head)? {
+            let reference = item?;
+            let priority = reference
+                .name()
+                .and_then(remote_of_ref)
+                .and_then(|remote| self
+                    .remote_priorities
+                    .iter()
+                    .position(|name| name == remote || name == "*")
+                );
+            let priority = match priority {
+                Some(priority) => priority,
+                None => continue,
+            };
+            if selected.as_ref().map(|s: &(usize, Reference)| priority < s.0).unwrap_or(true) {
+                selected = Some((priority, reference));
+            }
+        }
+
+        match selected {
+            Some((_, reference)) => reference
+                .peel(git2::ObjectType::Commit)
+                .map(|commit| Some(commit.id()))
+                .chain_err(|| EK::CannotGetCommit),
+            None => Ok(None),
+        }
+    }
+
     /// Find collectable references for multiple issues
     ///
     /// This is a convenience function.
@@ -229,6 +344,45 @@ impl<'r> CollectableRefs<'r>
 }
 
 
+/// Expire reflog entries of local dit references
+///
+/// This function removes all entries older than the time specified, in
+/// seconds since the epoch, from the reflogs of all references in the local
+/// dit namespace. The number of entries removed is returned.
+///
+pub fn expire_reflogs(repo: &git2::Repository, before: i64) -> Result<usize> {
+    let glob = "refs/dit/**";
+    let mut retval = 0;
+    for item in repo.references_glob(glob).chain_err(|| EK::CannotGetReferences(glob.to_owned()))? {
+        let reference = item.chain_err(|| EK::CannotGetReference)?;
+        let name = reference
+            .name()
+            .ok_or_else(|| Error::from_kind(EK::ReferenceNameError))?;
+        let mut reflog = repo
+            .reflog(name)
+            .chain_err(|| EK::CannotExpireReflog(name.to_owned()))?;
+
+        // Entries are removed back to front so the indices remain valid.
+        let expired: Vec<usize> = reflog
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.committer().when().seconds() < before)
+            .map(|(index, _)| index)
+            .collect();
+        for index in expired.iter().rev() {
+            reflog
+                .remove(*index, false)
+                .chain_err(|| EK::CannotExpireReflog(name.to_owned()))?;
+        }
+        if !expired.is_empty() {
+            reflog.write().chain_err(|| EK::CannotExpireReflog(name.to_owned()))?;
+        }
+        retval += expired.len();
+    }
+    Ok(retval)
+}
+
+
 
 
 #[cfg(test)]
@@ -310,5 +464,124 @@ mod tests {
         collected.sort();
         assert_eq!(refs_to_collect, collected);
     }
-}
 
+    #[test]
+    fn collectable_heads() {
+        let mut testing_repo = TestingRepo::new("collectable_heads");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let other_issue = repo
+            .create_issue(&sig, &sig, "Test message 3", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let remote_heads = [
+            ("origin", message.id()),
+            ("other", other_issue.id()),
+            ("mirror", issue.id()),
+        ];
+        for &(remote, target) in remote_heads.iter() {
+            repo.reference(
+                &format!("refs/remotes/{}/dit/{}/head", remote, issue.id()),
+                target,
+                false,
+                "create remote head"
+            ).expect("Could not create reference");
+        }
+
+        let collected_heads = |spec, prios: Vec<&str>| -> Vec<String> {
+            CollectableRefs::new(repo)
+                .collect_heads(spec)
+                .remote_priorities(prios.into_iter().map(String::from))
+                .for_issue(&issue)
+                .expect("Error during collection")
+                .map(|r| r.expect("Could not retrieve reference"))
+                .filter_map(|r| r.name().map(String::from))
+                .filter(|name| name.ends_with("/head"))
+                .collect()
+        };
+        let local_head = vec![format!("refs/dit/{}/head", issue.id())];
+
+        assert_eq!(collected_heads(ReferenceCollectionSpec::BackedByRemote("origin".to_owned()), vec![]), local_head);
+        assert!(collected_heads(ReferenceCollectionSpec::BackedByRemote("other".to_owned()), vec![]).is_empty());
+        assert_eq!(collected_heads(ReferenceCollectionSpec::Always, vec!["mirror"]), local_head);
+        assert!(collected_heads(ReferenceCollectionSpec::Always, vec!["origin", "*"]).is_empty());
+    }
+
+    #[test]
+    fn stale_remote_refs() {
+        let mut testing_repo = TestingRepo::new("stale_remote_refs");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        repo.remote("origin", "https://example.com/repo.git")
+            .expect("Could not create remote");
+        for remote in &["origin", "gone"] {
+            repo.reference(
+                &format!("refs/remotes/{}/dit/{}/head", remote, issue.id()),
+                issue.id(),
+                false,
+                "create remote head"
+            ).expect("Could not create reference");
+        }
+
+        let stale: Vec<_> = CollectableRefs::new(repo)
+            .stale_remote_refs()
+            .expect("Could not retrieve stale references")
+            .into_iter()
+            .filter_map(|r| r.name().map(String::from))
+            .collect();
+        assert_eq!(stale, vec![format!("refs/remotes/gone/dit/{}/head", issue.id())]);
+    }
+
+    #[test]
+    fn reflog_expiry() {
+        let mut testing_repo = TestingRepo::new("reflog_expiry");
+        let repo = testing_repo.repo();
+
+        repo.config()
+            .and_then(|mut config| config.set_str("core.logAllRefUpdates", "always"))
+            .expect("Could not configure repository");
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let refname = format!("refs/dit/{}/head", issue.id());
+        let entries = || repo.reflog(&refname).expect("Could not retrieve reflog").len();
+        assert!(entries() > 0);
+
+        let now = sig.when().seconds();
+        assert_eq!(expire_reflogs(repo, now - 60).expect("Could not expire reflogs"), 0);
+        let expected = entries();
+        assert_eq!(expire_reflogs(repo, now + 60).expect("Could not expire reflogs"), expected);
+        assert_eq!(entries(), 0);
+    }
+}
@@ -13,6 +13,7 @@
 //!
 
 use git2::{self, Commit, Oid, Reference, References};
+use std::collections::HashSet;
 use std::fmt;
 use std::hash;
 use std::result::Result as RResult;
@@ -22,6 +23,11 @@ use error::ErrorKind as EK;
 use iter::Messages;
 
 
+/// Key of the trailer identifying read marker commits
+///
+const SEEN_MARKER_KEY: &str = "Dit-seen-marker";
+
+
 #[derive(PartialEq)]
 pub enum IssueRefType {
     Any,
@@ -129,6 +135,12 @@ impl<'r> Issue<'r> {
         self.obj.id()
     }
 
+    /// Get the repository the issue resides in
+    ///
+    pub fn repo(&self) -> &'r git2::Repository {
+        self.repo
+    }
+
     /// Get the issue's initial message
     ///
     pub fn initial_message(&self) -> Result<git2::Commit<'r>> {
@@ -205,14 +217,34 @@ impl<'r> Issue<'r> {
     pub fn messages(&self) -> Result<Messages<'r>> {
         self.terminated_messages()
             .and_then(|mut messages| {
-                let glob = format!("**/dit/{}/**", self.ref_part());
-
                 // The iterator will iterate over all the messages in the tree
                 // spanned but it will halt at the initial message.
-                messages
-                    .revwalk
-                    .push_glob(glob.as_ref())
-                    .chain_err(|| EK::CannotGetReferences(glob))?;
+                // NOTE: We push the references individually rather than using
+                //       `Revwalk::push_glob()`, which implicitly prefixes the
+                //       glob with `refs/` and thus misses local references.
+                for item in self.all_refs(IssueRefType::Any)? {
+                    let id = item
+                        .chain_err(|| EK::CannotGetReference)?
+                        .peel(git2::ObjectType::Commit)
+                        .chain_err(|| EK::CannotGetCommit)?
+                        .id();
+                    messages
+                        .revwalk
+                        .push(id)
+                        .chain_err(|| EK::CannotConstructRevwalk)?;
+                }
+
+                // Messages of archived leaves are reachable via the parents of
+                // the archive commit. We don't push the commit itself since it
+                // is not a message.
+                if let Some(archive) = self.archive_commit()? {
+                    for parent in archive.parent_ids() {
+                        messages
+                            .revwalk
+                            .push(parent)
+                            .chain_err(|| EK::CannotConstructRevwalk)?;
+                    }
+                }
 
                 Ok(messages)
             })
@@ -305,6 +337,254 @@ impl<'r> Issue<'r> {
             .chain_err(|| EK::CannotSetReference(refname))
     }
 
+    /// Get the archive reference of the issue
+    ///
+    /// Returns the reference to the issue's archive commit, if the issue was
+    /// archived.
+    ///
+    pub fn archive_ref(&self) -> Option<Reference<'r>> {
+        self.repo
+            .find_reference(&format!("refs/dit-archive/{}", self.ref_part()))
+            .ok()
+    }
+
+    /// Check whether the issue was archived
+    ///
+    pub fn is_archived(&self) -> bool {
+        self.archive_ref().is_some()
+    }
+
+    /// Archive the issue's local leaves
+    ///
+    /// This function creates an archive commit with the local head as the
+    /// first parent and the messages referred to by the local leaves as
+    /// additional parents. Parents of a previous archive commit are retained.
+    /// The archive commit is referenced by `refs/dit-archive/<id>` and the
+    /// local leaf references are deleted. The messages remain reachable
+    /// through the archive reference.
+    ///
+    /// If the local head and leaves are already covered by the existing
+    /// archive commit, no new archive commit is created and the existing
+    /// archive reference is returned.
+    ///
+    pub fn archive(&self, sig: &git2::Signature) -> Result<Reference<'r>> {
+        let mut parents = Vec::new();
+        if let Ok(head) = self.local_head() {
+            parents.push(head.peel(git2::ObjectType::Commit).chain_err(|| EK::CannotGetCommit)?.id());
+        }
+        let archived: Vec<Oid> = self
+            .archive_commit()?
+            .map(|archive| archive.parent_ids().collect())
+            .unwrap_or_default();
+        parents.extend(archived.iter().cloned());
+        let mut leaves = Vec::new();
+        for item in self.local_refs(IssueRefType::Leaf)? {
+            let leaf = item.chain_err(|| EK::CannotGetReference)?;
+            parents.push(leaf.peel(git2::ObjectType::Commit).chain_err(|| EK::CannotGetCommit)?.id());
+            leaves.push(leaf);
+        }
+        let mut seen = HashSet::new();
+        parents.retain(|id| seen.insert(*id));
+
+        let retval = match self.archive_ref() {
+            Some(reference) if parents.iter().all(|id| archived.contains(id)) => reference,
+            _ => self.create_archive(sig, parents)?,
+        };
+
+        for mut leaf in leaves {
+            let name = leaf.name().unwrap_or_default().to_owned();
+            leaf.delete().chain_err(|| EK::CannotDeleteReference(name))?;
+        }
+
+        Ok(retval)
+    }
+
+    /// Create an archive commit with the given parents and reference it
+    ///
+    fn create_archive(&self, sig: &git2::Signature, parents: Vec<Oid>) -> Result<Reference<'r>> {
+        let parents: Vec<Commit> = parents
+            .into_iter()
+            .map(|id| self.repo.find_commit(id).chain_err(|| EK::CannotGetCommit))
+            .collect::<Result<_>>()?;
+        let parent_refs: Vec<&Commit> = parents.iter().collect();
+        let tree = self.repo
+            .treebuilder(None)
+            .and_then(|builder| builder.write())
+            .and_then(|id| self.repo.find_tree(id))
+            .chain_err(|| EK::CannotBuildTree)?;
+        let message = format!("Archive of issue {}", self);
+        let archive = self.repo
+            .commit(None, sig, sig, &message, &tree, &parent_refs)
+            .chain_err(|| EK::CannotCreateMessage)?;
+
+        let refname = format!("refs/dit-archive/{}", self.ref_part());
+        let reflogmsg = format!("git-dit: archive {}", self);
+        self.repo
+            .reference(&refname, archive, true, &reflogmsg)
+            .chain_err(|| EK::CannotSetReference(refname))
+    }
+
+    /// Get the archive commit of the issue, if any
+    ///
+    fn archive_commit(&self) -> Result<Option<Commit<'r>>> {
+        match self.archive_ref() {
+            Some(reference) => reference
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .into_commit()
+                .map(Some)
+                .map_err(|o| Error::from_kind(EK::CannotGetCommitForRev(o.id().to_string()))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the read marker of the issue
+    ///
+    /// Returns the reference marking the messages the local user has seen, if
+    /// the issue was ever marked as read.
+    ///
+    pub fn seen_ref(&self) -> Option<Reference<'r>> {
+        self.repo
+            .find_reference(&format!("refs/dit-seen/{}", self.ref_part()))
+            .ok()
+    }
+
+    /// Get all messages of the issue the local user has not seen yet
+    ///
+    /// If the issue was never marked as read, all messages are returned.
+    ///
+    pub fn unread_messages(&self) -> Result<Messages<'r>> {
+        let mut messages = self.messages()?;
+        if let Some(marker) = self.seen_ref() {
+            let commit = marker
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .into_commit()
+                .map_err(|o| Error::from_kind(EK::CannotGetCommitForRev(o.id().to_string())))?;
+
+            // The marker refers either to the newest message seen or to a
+            // marker commit with the messages seen as parents.
+            for id in Some(commit.id()).into_iter().chain(commit.parent_ids()) {
+                messages
+                    .revwalk
+                    .hide(id)
+                    .chain_err(|| EK::CannotConstructRevwalk)?;
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Mark all messages of the issue as read
+    ///
+    /// This function sets the issue's read marker `refs/dit-seen/<id>`. If all
+    /// messages are reachable from a single message, the marker refers to that
+    /// message. Otherwise, a marker commit is created with the tips of the
+    /// issue's message tree as parents. The marker is not a dit reference and
+    /// is hence never pushed.
+    ///
+    pub fn mark_read(&self, sig: &git2::Signature) -> Result<Reference<'r>> {
+        let mut tips = Vec::new();
+        for item in self.all_refs(IssueRefType::Any)? {
+            tips.push(item
+                .chain_err(|| EK::CannotGetReference)?
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .id());
+        }
+        if let Some(archive) = self.archive_commit()? {
+            tips.extend(archive.parent_ids());
+        }
+        self.set_seen(sig, tips)
+    }
+
+    /// Mark a message of the issue as read
+    ///
+    /// In addition to the messages already marked as read, the message supplied
+    /// and the messages it is based on are considered read afterwards, e.g.
+    /// after the local user authored the message. Other messages remain
+    /// unread.
+    ///
+    pub fn mark_message_read(&self, sig: &git2::Signature, message: Oid) -> Result<Reference<'r>> {
+        let mut tips = match self.seen_ref() {
+            Some(marker) => {
+                let commit = marker
+                    .peel(git2::ObjectType::Commit)
+                    .chain_err(|| EK::CannotGetCommit)?
+                    .into_commit()
+                    .map_err(|o| Error::from_kind(EK::CannotGetCommitForRev(o.id().to_string())))?;
+                if self.is_seen_marker(&commit) {
+                    commit.parent_ids().collect()
+                } else {
+                    vec![commit.id()]
+                }
+            },
+            None => Vec::new(),
+        };
+        tips.push(message);
+        self.set_seen(sig, tips)
+    }
+
+    /// Set the read marker to the tips supplied
+    ///
+    /// If a single tip remains after removing tips reachable from other tips,
+    /// the marker refers to that tip. Otherwise, a marker commit is created
+    /// with the remaining tips as parents.
+    ///
+    fn set_seen(&self, sig: &git2::Signature, mut tips: Vec<Oid>) -> Result<Reference<'r>> {
+        let mut seen = HashSet::new();
+        tips.retain(|id| seen.insert(*id));
+
+        // Tips reachable from other tips are redundant.
+        let candidates = tips.clone();
+        tips.retain(|tip| !candidates
+            .iter()
+            .any(|other| self.repo.graph_descendant_of(*other, *tip).unwrap_or(false)));
+
+        let target = if tips.len() == 1 {
+            tips[0]
+        } else {
+            let parents: Vec<Commit> = tips
+                .into_iter()
+                .map(|id| self.repo.find_commit(id).chain_err(|| EK::CannotGetCommit))
+                .collect::<Result<_>>()?;
+            let parent_refs: Vec<&Commit> = parents.iter().collect();
+            let tree = self.repo
+                .treebuilder(None)
+                .and_then(|builder| builder.write())
+                .and_then(|id| self.repo.find_tree(id))
+                .chain_err(|| EK::CannotBuildTree)?;
+            self.repo
+                .commit(None, sig, sig, &self.seen_message(), &tree, &parent_refs)
+                .chain_err(|| EK::CannotCreateMessage)?
+        };
+
+        let refname = format!("refs/dit-seen/{}", self.ref_part());
+        let reflogmsg = format!("git-dit: mark {} as read", self);
+        self.repo
+            .reference(&refname, target, true, &reflogmsg)
+            .chain_err(|| EK::CannotSetReference(refname))
+    }
+
+    /// Get the message of the issue's read marker commits
+    ///
+    /// The message carries a trailer identifying the commit as a read marker
+    /// of the issue.
+    ///
+    fn seen_message(&self) -> String {
+        format!("Messages of issue {} seen\n\n{}: {}", self, SEEN_MARKER_KEY, self)
+    }
+
+    /// Check whether a commit is a read marker commit of the issue
+    ///
+    fn is_seen_marker(&self, commit: &Commit) -> bool {
+        use message::Message;
+
+        let id = self.id().to_string();
+        commit
+            .trailers()
+            .any(|trailer| trailer.key.as_ref() == SEEN_MARKER_KEY && trailer.value.to_string() == id)
+    }
+
     /// Get reference part for this issue
     ///
     /// The references associated with an issue reside in paths specific to the
@@ -537,5 +817,264 @@ mod tests {
             .expect("Could not update head reference");
         assert_eq!(issue.local_head().unwrap().target().unwrap(), message.id());
     }
+
+    #[test]
+    fn archive() {
+        let mut testing_repo = TestingRepo::new("archive");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        assert!(!issue.is_archived());
+
+        issue.archive(&sig).expect("Could not archive issue");
+        assert!(issue.is_archived());
+        assert!(issue
+            .local_refs(IssueRefType::Leaf)
+            .expect("Could not retrieve local leaves")
+            .next()
+            .is_none());
+
+        let mut ids: Vec<_> = issue
+            .messages()
+            .expect("Could not retrieve messages")
+            .map(|message| message.expect("Could not retrieve message").id())
+            .collect();
+        ids.sort();
+        let mut expected = vec![issue.id(), message1.id(), message2.id()];
+        expected.sort();
+        assert_eq!(ids, expected);
+
+        // archiving again retains the previously archived messages
+        let message3 = issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&message1])
+            .expect("Could not add message");
+        issue.archive(&sig).expect("Could not archive issue");
+        assert_eq!(issue.messages().expect("Could not retrieve messages").count(), 4);
+        assert!(issue
+            .messages()
+            .expect("Could not retrieve messages")
+            .any(|message| message.expect("Could not retrieve message").id() == message3.id()));
+    }
+
+    #[test]
+    fn archive_idempotent() {
+        let mut testing_repo = TestingRepo::new("archive_idempotent");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+
+        let first = issue
+            .archive(&sig)
+            .expect("Could not archive issue")
+            .target()
+            .expect("Could not get archive target");
+        let second = issue
+            .archive(&sig)
+            .expect("Could not archive issue")
+            .target()
+            .expect("Could not get archive target");
+        assert_eq!(first, second);
+
+        // a new message does require a new archive
+        let message = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let third = issue
+            .archive(&sig)
+            .expect("Could not archive issue")
+            .target()
+            .expect("Could not get archive target");
+        assert_ne!(third, second);
+        assert!(repo
+            .find_commit(third)
+            .expect("Could not find archive commit")
+            .parent_ids()
+            .any(|id| id == message.id()));
+    }
+
+    #[test]
+    fn read_markers() {
+        let mut testing_repo = TestingRepo::new("read_markers");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        assert!(issue.seen_ref().is_none());
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 2);
+
+        // a single line of messages is marked via the newest message
+        let marker = issue.mark_read(&sig).expect("Could not mark issue as read");
+        assert_eq!(marker.target(), Some(message1.id()));
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+
+        // diverging messages are marked via a marker commit
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let unread: Vec<_> = issue
+            .unread_messages()
+            .expect("Could not retrieve messages")
+            .map(|message| message.expect("Could not retrieve message").id())
+            .collect();
+        assert_eq!(unread, vec![message2.id()]);
+
+        let marker = issue.mark_read(&sig).expect("Could not mark issue as read");
+        assert_ne!(marker.target(), Some(message2.id()));
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+    }
+
+    #[test]
+    fn message_read_markers() {
+        let mut testing_repo = TestingRepo::new("message_read_markers");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        issue
+            .mark_message_read(&sig, initial_message.id())
+            .expect("Could not mark message as read");
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+
+        // messages on other branches remain unread
+        let message1 = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue
+            .mark_message_read(&sig, message2.id())
+            .expect("Could not mark message as read");
+        let unread: Vec<_> = issue
+            .unread_messages()
+            .expect("Could not retrieve messages")
+            .map(|message| message.expect("Could not retrieve message").id())
+            .collect();
+        assert_eq!(unread, vec![message1.id()]);
+
+        // marking further messages retains the messages marked before
+        let message3 = issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&message1])
+            .expect("Could not add message");
+        issue
+            .mark_message_read(&sig, message3.id())
+            .expect("Could not mark message as read");
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+        let message4 = issue
+            .add_message(&sig, &sig, "Test message 5", &empty_tree, vec![&message2])
+            .expect("Could not add message");
+        issue
+            .mark_message_read(&sig, message4.id())
+            .expect("Could not mark message as read");
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+    }
+
+    #[test]
+    fn read_marker_detection() {
+        let mut testing_repo = TestingRepo::new("read_marker_detection");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+
+        // a message resembling a marker commit is not mistaken for one
+        let text = format!("Messages of issue {} seen", issue);
+        let message1 = issue
+            .add_message(&sig, &sig, &text, &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        issue
+            .mark_message_read(&sig, message1.id())
+            .expect("Could not mark message as read");
+        let message2 = issue
+            .add_message(&sig, &sig, "Test message 3", &empty_tree, vec![&initial_message])
+            .expect("Could not add message");
+        let marker = issue
+            .mark_message_read(&sig, message2.id())
+            .expect("Could not mark message as read");
+        assert_eq!(issue.unread_messages().expect("Could not retrieve messages").count(), 0);
+
+        // marker commits are recognized as such
+        let message3 = issue
+            .add_message(&sig, &sig, "Test message 4", &empty_tree, vec![&message2])
+            .expect("Could not add message");
+        issue
+            .mark_message_read(&sig, message3.id())
+            .expect("Could not mark message as read");
+        let parents: Vec<_> = issue
+            .seen_ref()
+            .expect("Could not find read marker")
+            .peel(git2::ObjectType::Commit)
+            .expect("Could not retrieve read marker commit")
+            .into_commit()
+            .expect("Read marker is not a commit")
+            .parent_ids()
+            .collect();
+        assert!(!parents.contains(&marker.target().expect("Read marker is not direct")));
+        assert_eq!(parents.len(), 2);
+    }
 }
 
@@ -157,6 +157,42 @@ impl<'a, I> MessagesExt for I
 }
 
 
+/// Sort messages in chronological order
+///
+/// This function removes duplicates from the messages provided and sorts them
+/// by their time. Messages with equal times are sorted topologically, e.g. a
+/// reply will always succeed the message it replies to.
+///
+pub fn chronological<'r, I>(repo: &'r Repository, messages: I) -> Result<Vec<git2::Commit<'r>>>
+    where I: IntoIterator<Item = git2::Commit<'r>>
+{
+    let mut messages: HashMap<_, _> = messages
+        .into_iter()
+        .map(|message| (message.id(), message))
+        .collect();
+
+    let mut revwalk = repo.revwalk().chain_err(|| EK::CannotConstructRevwalk)?;
+    revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE);
+    for message in messages.values() {
+        revwalk.push(message.id())?;
+        for parent in message.parent_ids().filter(|id| !messages.contains_key(id)) {
+            revwalk.hide(parent)?;
+        }
+    }
+
+    let mut sorted = Vec::new();
+    for id in revwalk {
+        if let Some(message) = messages.remove(&id.chain_err(|| EK::CannotGetCommit)?) {
+            sorted.push(message);
+        }
+    }
+
+    // The sort is stable, preserving the topological order for equal times.
+    sorted.sort_by_key(|message| message.time());
+    Ok(sorted)
+}
+
+
 /// Iterator iterating over messages of an issue
 ///
 /// This iterator returns the first parent of a commit or message successively
@@ -458,5 +494,58 @@ mod tests {
         refs_to_report.sort();
         assert_eq!(reported, refs_to_report);
     }
+
+    // chronological tests
+
+    #[test]
+    fn chronological_messages() {
+        let mut testing_repo = TestingRepo::new("chronological_messages");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::new("Foo Bar", "foo.bar@example.com", &git2::Time::new(0, 0))
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let commit = |message: &str, parents: &[&git2::Commit]| repo
+            .commit(None, &sig, &sig, message, &empty_tree, parents)
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+
+        // An issue marked as a duplicate of another one, referred to by the
+        // original issue. All the messages have the same time.
+        let original = commit("Test message 1", &[]);
+        let reply = commit("Test message 2", &[&original]);
+        let duplicate = commit("Test message 3", &[]);
+        let marker = commit("Test message 4", &[&duplicate]);
+        let referring = commit("Test message 5", &[&reply, &marker]);
+
+        // Supply the messages in revwalk order, children first
+        let messages = vec![
+            referring.clone(),
+            marker.clone(),
+            reply.clone(),
+            original.clone(),
+            marker.clone(),
+            duplicate.clone(),
+        ];
+
+        let sorted: Vec<_> = chronological(repo, messages)
+            .expect("Could not sort messages")
+            .into_iter()
+            .map(|message| message.id())
+            .collect();
+        assert_eq!(sorted.len(), 5);
+
+        let position = |message: &git2::Commit| sorted
+            .iter()
+            .position(|id| *id == message.id())
+            .expect("Message missing");
+        assert!(position(&original) < position(&reply));
+        assert!(position(&duplicate) < position(&marker));
+        assert!(position(&reply) < position(&referring));
+        assert!(position(&marker) < position(&referring));
+    }
 }
 
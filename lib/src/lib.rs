@@ -35,18 +35,30 @@
 //!
 
 #[macro_use] extern crate error_chain;
+#[macro_use] extern crate is_match;
 #[macro_use] extern crate lazy_static;
 extern crate git2;
 extern crate regex;
 
+pub mod attachment;
+pub mod backlinks;
+pub mod changes;
+pub mod deps;
+pub mod draft;
 pub mod error;
+pub mod fixes;
+pub mod fsck;
 pub mod gc;
 pub mod issue;
 pub mod iter;
 pub mod message;
+pub mod plugin;
 pub mod remote;
 pub mod repository;
+pub mod template;
 pub mod trailer;
+pub mod transfer;
+pub mod xref;
 
 mod utils;
 
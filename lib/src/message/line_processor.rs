@@ -49,6 +49,45 @@ impl<I, S> Iterator for Quoted<I, S>
 }
 
 
+/// Comment wrapper for iterators over strings
+///
+/// This iterator wrapps an iterator over lines as string-like items. It
+/// returns the lines prefixed with a "#", turning them into comments which will
+/// be removed by the `WithoutCommentsIter`.
+///
+#[derive(Debug)]
+pub struct Commented<I, S>(I)
+    where I: Iterator<Item = S>,
+          S: AsRef<str>;
+
+impl<I, J, S> From<I> for Commented<J, S>
+    where I: IntoIterator<Item = S, IntoIter = J>,
+          J: Iterator<Item = S>,
+          S: AsRef<str>
+{
+    fn from(lines: I) -> Self {
+        Commented(lines.into_iter())
+    }
+}
+
+impl<I, S> Iterator for Commented<I, S>
+    where I: Iterator<Item = S>,
+          S: AsRef<str>
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|l| {
+            let line = l.as_ref();
+            match line.is_empty() {
+                true  => String::from("#"),
+                false => format!("# {}", line),
+            }
+        })
+    }
+}
+
+
 /// An Iterator type which iterates over String objects, used to strip
 /// whitespace from an iterator over String.
 ///
@@ -238,6 +277,13 @@ mod tests {
         assert!(!lines.next().is_some());
     }
 
+    #[test]
+    fn commented_lines() {
+        let lines: Vec<_> = Commented::from(vec!["foo", "", "bar"]).collect();
+        assert_eq!(lines, vec!["# foo", "#", "# bar"]);
+        assert!(WithoutCommentsIter::from(lines).next().is_none());
+    }
+
     #[test]
     fn left_stripped_lines() {
         let mut lines = StripWhiteSpaceLeftIter::from(vec!["foo  ", "  bar", "  ", ""]);
@@ -27,7 +27,7 @@ use std;
 pub mod block;
 pub mod line_processor;
 
-use self::line_processor::{Quoted, StrippingIter};
+use self::line_processor::{Commented, Quoted, StrippingIter};
 
 
 /// Special iterator extension for messages
@@ -67,6 +67,13 @@ pub trait LineIteratorExt<S>
     ///
     fn quoted(self) -> Quoted<Self::Iter, S>;
 
+    /// Create an iterator for commenting out lines
+    ///
+    /// The iterator returned will prepend a `#` and, in the case of non-empty
+    /// lines, a space, to each item.
+    ///
+    fn commented(self) -> Commented<Self::Iter, S>;
+
     /// Create an iterator over categorized blocks
     ///
     /// The iterator returned by this function provides a line-block oriented
@@ -117,6 +124,10 @@ impl<L, S> LineIteratorExt<S> for L
         Quoted::from(self)
     }
 
+    fn commented(self) -> Commented<Self::Iter, S> {
+        Commented::from(self)
+    }
+
     fn line_blocks(self) -> block::Blocks<Self::Iter, S> {
         block::Blocks::from(self)
     }
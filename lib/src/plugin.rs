@@ -0,0 +1,211 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Plugin support
+//!
+//! Unknown subcommands are delegated to executables named `git-dit-<name>`.
+//! Such plugins are invoked with an environment describing the context of the
+//! invocation: the repository, the issue specified on the command line, the
+//! priorization of remotes and the output format requested. This module
+//! provides the `PluginContext` type for representing that context, both for
+//! exporting it to and for parsing it in plugins.
+//!
+
+use git2::{self, Oid};
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::result::Result as RResult;
+use std::str::FromStr;
+
+use issue::Issue;
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Variable holding the path of the repository's git directory
+///
+pub const REPO_VAR: &str = "GIT_DIT_REPO";
+
+/// Variable holding the id of the issue specified on the command line
+///
+pub const ISSUE_VAR: &str = "GIT_DIT_ISSUE";
+
+/// Variable holding the comma-separated names of remotes, in descending order
+/// of priority
+///
+pub const REMOTE_PRIOS_VAR: &str = "GIT_DIT_REMOTE_PRIOS";
+
+/// Variable holding the output format requested
+///
+pub const FORMAT_VAR: &str = "GIT_DIT_FORMAT";
+
+
+/// Output format requested from a plugin
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(Error::from_kind(EK::MalformedPluginVariable(FORMAT_VAR.to_owned()))),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        f.write_str(match *self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+        })
+    }
+}
+
+
+/// Context in which a plugin is invoked
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PluginContext {
+    /// Path of the repository's git directory, if any
+    pub repo: Option<PathBuf>,
+    /// Issue specified on the command line, if any
+    pub issue: Option<Oid>,
+    /// Names of remotes, in descending order of priority
+    pub remote_prios: Vec<String>,
+    /// Output format requested
+    pub format: OutputFormat,
+}
+
+impl PluginContext {
+    /// Retrieve the context from the environment of the current process
+    ///
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Retrieve the context from variables provided by a function
+    ///
+    /// Variables which are not set are replaced by defaults.
+    ///
+    pub fn from_vars<F>(var: F) -> Result<Self>
+        where F: Fn(&str) -> Option<String>
+    {
+        let issue = match var(ISSUE_VAR) {
+            Some(id) => Some(Oid::from_str(&id)
+                .chain_err(|| EK::MalformedPluginVariable(ISSUE_VAR.to_owned()))?),
+            None => None,
+        };
+        let remote_prios = var(REMOTE_PRIOS_VAR)
+            .unwrap_or_else(|| "*".to_owned())
+            .split(',')
+            .map(String::from)
+            .collect();
+        let format = match var(FORMAT_VAR) {
+            Some(format) => format.parse()?,
+            None => OutputFormat::default(),
+        };
+
+        Ok(PluginContext {
+            repo: var(REPO_VAR).map(PathBuf::from),
+            issue,
+            remote_prios,
+            format,
+        })
+    }
+
+    /// Get the variables representing the context
+    ///
+    /// Variables for parts of the context which are not present are omitted.
+    ///
+    pub fn vars(&self) -> Vec<(&'static str, String)> {
+        let mut retval = Vec::new();
+        if let Some(ref repo) = self.repo {
+            retval.push((REPO_VAR, repo.to_string_lossy().into_owned()));
+        }
+        if let Some(issue) = self.issue {
+            retval.push((ISSUE_VAR, issue.to_string()));
+        }
+        retval.push((REMOTE_PRIOS_VAR, self.remote_prios.join(",")));
+        retval.push((FORMAT_VAR, self.format.to_string()));
+        retval
+    }
+
+    /// Open the repository
+    ///
+    /// If no repository is part of the context, the repository is discovered
+    /// like git would.
+    ///
+    pub fn open_repo(&self) -> Result<git2::Repository> {
+        match self.repo {
+            Some(ref path) => git2::Repository::open(path),
+            None => git2::Repository::open_from_env(),
+        }.map_err(Error::from)
+    }
+
+    /// Get the issue specified on the command line, if any
+    ///
+    pub fn issue<'r>(&self, repo: &'r git2::Repository) -> Result<Option<Issue<'r>>> {
+        match self.issue {
+            Some(id) => repo.find_issue(id).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // PluginContext tests
+
+    #[test]
+    fn context_from_vars() {
+        let context = PluginContext {
+            repo: Some(PathBuf::from("/tmp/repo/.git")),
+            issue: Some(Oid::from_str("65b56706fdc3501749d008750c61a1f24b888f72").unwrap()),
+            remote_prios: vec!["origin".to_owned(), "*".to_owned()],
+            format: OutputFormat::Json,
+        };
+        let vars: HashMap<_, _> = context.vars().into_iter().collect();
+        assert_eq!(vars.get(REMOTE_PRIOS_VAR).map(String::as_str), Some("origin,*"));
+        let parsed = PluginContext::from_vars(|name| vars.get(name).cloned())
+            .expect("Could not parse context");
+        assert_eq!(parsed, context);
+
+        // defaults
+        let parsed = PluginContext::from_vars(|_| None).expect("Could not parse context");
+        assert_eq!(parsed.repo, None);
+        assert_eq!(parsed.issue, None);
+        assert_eq!(parsed.remote_prios, vec!["*".to_owned()]);
+        assert_eq!(parsed.format, OutputFormat::Text);
+
+        let malformed = PluginContext::from_vars(|name| if name == FORMAT_VAR {
+            Some("xml".to_owned())
+        } else {
+            None
+        });
+        assert!(malformed.is_err());
+    }
+}
@@ -9,6 +9,8 @@
 
 //! Module providing extension trait for remotes
 //!
+//! This module also provides utilities for remote tracking references.
+//!
 
 use git2::Remote;
 
@@ -43,3 +45,16 @@ impl<'r> RemoteExt for Remote<'r> {
     }
 }
 
+
+/// Extract the name of the remote from a remote tracking reference's name
+///
+/// If the name is not the name of a remote tracking reference, i.e. a
+/// reference in the `refs/remotes/` namespace, `None` is returned.
+///
+pub fn remote_of_ref(name: &str) -> Option<&str> {
+    let mut parts = name.splitn(4, '/');
+    match (parts.next(), parts.next()) {
+        (Some("refs"), Some("remotes")) => parts.next(),
+        _ => None,
+    }
+}
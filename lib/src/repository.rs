@@ -16,6 +16,7 @@
 use git2::{self, Commit, Oid, Tree};
 use std::collections::HashSet;
 
+use fixes;
 use gc;
 use issue::Issue;
 use iter;
@@ -100,6 +101,10 @@ pub trait RepositoryExt {
     ///
     fn collectable_refs<'a>(&'a self) -> gc::CollectableRefs<'a>;
 
+    /// Produce a FixTracker
+    ///
+    fn fix_tracker<'a>(&'a self) -> Result<fixes::FixTracker<'a>>;
+
     /// Get an empty tree
     ///
     /// This function returns an empty tree.
@@ -205,6 +210,10 @@ impl RepositoryExt for git2::Repository {
         gc::CollectableRefs::new(self)
     }
 
+    fn fix_tracker<'a>(&'a self) -> Result<fixes::FixTracker<'a>> {
+        fixes::FixTracker::new(self)
+    }
+
     fn issue_messages_iter<'a>(&'a self, commit: Commit<'a>) -> Result<iter::IssueMessagesIter<'a>> {
         self.first_parent_messages(commit.id()).map(iter::Messages::until_any_initial)
     }
@@ -0,0 +1,121 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Message templates
+//!
+//! A template supplies the initial content of a message, e.g. a prefilled
+//! subject, a skeleton for the body and trailers, for a specific kind of
+//! issue such as bug reports. Templates may contain comment lines, which are
+//! stripped along with other comments after editing. Comment lines of the form
+//! `# Required: <key>[, <key>...]` declare trailers which are required to be
+//! present, with a non-empty value, in messages created from the template.
+//!
+
+use message::LineIteratorExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Prefix of comment lines declaring required trailers
+///
+const REQUIRED_PREFIX: &str = "# Required:";
+
+
+/// Message template
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Template {
+    lines: Vec<String>,
+    required: Vec<String>,
+}
+
+impl Template {
+    /// Create a template from its lines
+    ///
+    pub fn from_lines<I, S>(lines: I) -> Self
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        let lines: Vec<String> = lines.into_iter().map(Into::into).collect();
+        let required = lines
+            .iter()
+            .filter(|line| line.starts_with(REQUIRED_PREFIX))
+            .flat_map(|line| line[REQUIRED_PREFIX.len()..].split(','))
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(String::from)
+            .collect();
+
+        Template { lines, required }
+    }
+
+    /// Get the lines of the template, including comments
+    ///
+    pub fn lines(&self) -> &[String] {
+        self.lines.as_ref()
+    }
+
+    /// Get the keys of the trailers required
+    ///
+    pub fn required_trailers(&self) -> &[String] {
+        self.required.as_ref()
+    }
+
+    /// Check whether a message contains all the trailers required
+    ///
+    /// The message should already be stripped of comments. A required trailer
+    /// is only considered present if its value is non-empty.
+    ///
+    pub fn check<I, S>(&self, message: I) -> Result<()>
+        where I: IntoIterator<Item = S>,
+              S: AsRef<str>
+    {
+        let trailers: Vec<_> = message
+            .into_iter()
+            .trailers()
+            .filter(|trailer| !trailer.value.to_string().is_empty())
+            .map(|trailer| trailer.key)
+            .collect();
+
+        match self.required.iter().find(|key| !trailers.iter().any(|t| t.as_ref() == *key)) {
+            Some(key) => Err(Error::from_kind(EK::MissingTrailer(key.clone()))),
+            None => Ok(()),
+        }
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Template tests
+
+    #[test]
+    fn required_trailers() {
+        let template = Template::from_lines(vec![
+            "Bug: ",
+            "",
+            "# Describe the bug",
+            "# Required: Dit-type, Dit-severity",
+            "",
+            "Dit-type: bug",
+            "Dit-severity:",
+        ]);
+        assert_eq!(template.lines().len(), 7);
+        assert_eq!(template.required_trailers(), &["Dit-type".to_owned(), "Dit-severity".to_owned()]);
+
+        assert!(template.check(vec!["Bug: foo", "", "Dit-type: bug", "Dit-severity: high"]).is_ok());
+        assert!(template.check(vec!["Bug: foo", "", "Dit-type: bug", "Dit-severity:"]).is_err());
+        assert!(template.check(vec!["Bug: foo", "", "Dit-type: bug"]).is_err());
+    }
+}
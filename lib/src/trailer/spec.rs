@@ -12,6 +12,9 @@
 //! This module provides a type for convenient metadata specification as well as
 //! well as specifications for some dit metadata tags.
 //!
+//! The specifications of all metadata tags known to dit are available via
+//! `KNOWN_SPECS`.
+//!
 
 use std::borrow::Borrow;
 use std::iter::FromIterator;
@@ -53,6 +56,98 @@ pub const ISSUE_STATUS_SPEC: TrailerSpec = TrailerSpec {
     accumulation: AccumulationPolicy::Latest,
 };
 
+/// Metadata specification for the person assigned to an issue
+///
+pub const ASSIGNEE_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-assignee",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for commits fixing an issue
+///
+pub const FIXES_SPEC: TrailerSpec = TrailerSpec {
+    key: "Fixes",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Metadata specification for the issue an issue duplicates
+///
+pub const DUPLICATE_OF_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-duplicate-of",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for issues split off from an issue
+///
+pub const SPLIT_TO_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-split-to",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Metadata specification for the location an issue was moved to
+///
+pub const MOVED_TO_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-moved-to",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for the location an issue was moved from
+///
+pub const MOVED_FROM_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-moved-from",
+    accumulation: AccumulationPolicy::Latest,
+};
+
+/// Metadata specification for references to issues in other repositories
+///
+pub const ISSUE_REF_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-ref",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Metadata specification for issues an issue depends on
+///
+pub const DEPENDS_ON_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-depends-on",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Metadata specification for issues blocked by an issue
+///
+pub const BLOCKS_SPEC: TrailerSpec = TrailerSpec {
+    key: "Dit-blocks",
+    accumulation: AccumulationPolicy::List,
+};
+
+/// Specifications of all trailers known to dit
+///
+pub const KNOWN_SPECS: &[TrailerSpec<'static>] = &[
+    ISSUE_TYPE_SPEC,
+    ISSUE_STATUS_SPEC,
+    ASSIGNEE_SPEC,
+    FIXES_SPEC,
+    DUPLICATE_OF_SPEC,
+    SPLIT_TO_SPEC,
+    MOVED_TO_SPEC,
+    MOVED_FROM_SPEC,
+    ISSUE_REF_SPEC,
+    DEPENDS_ON_SPEC,
+    BLOCKS_SPEC,
+];
+
+/// Specifications of trailers referring to commits or issues by their id
+///
+pub const REFERENCE_SPECS: &[TrailerSpec<'static>] = &[
+    FIXES_SPEC,
+    DUPLICATE_OF_SPEC,
+    SPLIT_TO_SPEC,
+    MOVED_TO_SPEC,
+    MOVED_FROM_SPEC,
+    ISSUE_REF_SPEC,
+    DEPENDS_ON_SPEC,
+    BLOCKS_SPEC,
+];
+
 
 /// Construct an accumulation map from a set of MetadataSpecifications
 ///
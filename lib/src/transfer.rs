@@ -0,0 +1,218 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Issue transfer utilities
+//!
+//! This module provides utilities for preparing the transfer of an issue into
+//! another repository. An issue may either be transferred as is, retaining the
+//! ids of all messages, or as a copy re-parented onto an empty root. In the
+//! latter case, the copy does not refer to any commit other than the issue's
+//! messages and does hence not drag along the history of the source
+//! repository.
+//!
+
+use git2::{self, Oid};
+use std::collections::{HashMap, HashSet};
+use std::result::Result as RResult;
+
+use issue::{Issue, IssueRefType};
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Prepared transfer of an issue
+///
+/// The transfer maps each message of the issue to the message which will end
+/// up in the target repository. For transfers retaining message ids, this is
+/// the identity.
+///
+pub struct Transfer<'r> {
+    issue: Issue<'r>,
+    mapping: HashMap<Oid, Oid>,
+}
+
+impl<'r> Transfer<'r> {
+    /// Prepare the transfer of an issue retaining all message ids
+    ///
+    pub fn new(issue: Issue<'r>) -> Self {
+        Transfer { issue, mapping: HashMap::new() }
+    }
+
+    /// Prepare the transfer of a re-parented copy of an issue
+    ///
+    /// This function creates a copy of each message of the issue in the
+    /// repository. Parents of a message which are not messages of the issue,
+    /// e.g. references to other issues or regular commits, are omitted and all
+    /// copies have an empty tree. The copies are not referenced by any
+    /// reference.
+    ///
+    pub fn reparented(issue: Issue<'r>) -> Result<Self> {
+        let repo = issue.repo();
+        let messages = issue
+            .messages()?
+            .map(|item| item.map(|message| message.id()))
+            .collect::<Result<HashSet<Oid>>>()?;
+        let tree = repo.empty_tree()?;
+
+        let mut mapping = HashMap::new();
+        for id in messages.iter() {
+            // We need to copy the parents of a message before we can copy the
+            // message itself.
+            let mut stack = vec![*id];
+            while let Some(current) = stack.last().cloned() {
+                if mapping.contains_key(&current) {
+                    stack.pop();
+                    continue;
+                }
+
+                let message = repo
+                    .find_commit(current)
+                    .chain_err(|| EK::CannotGetCommit)?;
+                let parents: Vec<Oid> = message
+                    .parent_ids()
+                    .filter(|parent| messages.contains(parent))
+                    .collect();
+                let pending: Vec<Oid> = parents
+                    .iter()
+                    .filter(|parent| !mapping.contains_key(*parent))
+                    .cloned()
+                    .collect();
+                if !pending.is_empty() {
+                    stack.extend(pending);
+                    continue;
+                }
+
+                let parents = parents
+                    .iter()
+                    .map(|parent| repo.find_commit(mapping[parent]))
+                    .collect::<RResult<Vec<_>, _>>()
+                    .chain_err(|| EK::CannotGetCommit)?;
+                let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+                let copy = repo
+                    .commit(None,
+                            &message.author(),
+                            &message.committer(),
+                            message.message_raw().unwrap_or_default(),
+                            &tree,
+                            &parent_refs)
+                    .chain_err(|| EK::CannotCreateMessage)?;
+                mapping.insert(current, copy);
+                stack.pop();
+            }
+        }
+
+        Ok(Transfer { issue, mapping })
+    }
+
+    /// Get the issue to transfer
+    ///
+    pub fn issue(&self) -> &Issue<'r> {
+        &self.issue
+    }
+
+    /// Get the id the issue will have in the target repository
+    ///
+    pub fn issue_id(&self) -> Oid {
+        self.map(self.issue.id())
+    }
+
+    /// Map a message of the issue to the message transferred
+    ///
+    pub fn map(&self, message: Oid) -> Oid {
+        self.mapping.get(&message).cloned().unwrap_or(message)
+    }
+
+    /// Get the references to create in the target repository
+    ///
+    /// This function returns the names and targets of the references to
+    /// create for the issue in the target repository: a head reference
+    /// referring to the message supplied and a leaf reference for each leaf of
+    /// the issue, local or remote.
+    ///
+    pub fn refs(&self, head: Oid) -> Result<Vec<(String, Oid)>> {
+        let prefix = format!("refs/dit/{}", self.issue_id());
+        let mut retval = vec![(format!("{}/head", prefix), head)];
+
+        let mut leaves = HashSet::new();
+        leaves.insert(head);
+        for item in self.issue.all_refs(IssueRefType::Leaf)? {
+            let leaf = item
+                .chain_err(|| EK::CannotGetReference)?
+                .peel(git2::ObjectType::Commit)
+                .chain_err(|| EK::CannotGetCommit)?
+                .id();
+            let leaf = self.map(leaf);
+            if leaves.insert(leaf) {
+                retval.push((format!("{}/leaves/{}", prefix, leaf), leaf));
+            }
+        }
+
+        Ok(retval)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // Transfer tests
+
+    #[test]
+    fn reparented_transfer() {
+        let mut testing_repo = TestingRepo::new("reparented_transfer");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let commit = repo
+            .commit(None, &sig, &sig, "Test commit", &empty_tree, &[])
+            .and_then(|id| repo.find_commit(id))
+            .expect("Could not create commit");
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![&commit])
+            .expect("Could not create issue");
+        let initial_message = issue
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let message = issue
+            .add_message(&sig, &sig, "Test message 2", &empty_tree, vec![&initial_message, &commit])
+            .expect("Could not add message");
+
+        let transfer = Transfer::reparented(issue).expect("Could not prepare transfer");
+        assert_ne!(transfer.issue_id(), initial_message.id());
+
+        let initial_copy = repo
+            .find_commit(transfer.issue_id())
+            .expect("Could not find copy of initial message");
+        assert_eq!(initial_copy.message(), initial_message.message());
+        assert_eq!(initial_copy.parent_count(), 0);
+
+        let message_copy = repo
+            .find_commit(transfer.map(message.id()))
+            .expect("Could not find copy of message");
+        assert_eq!(message_copy.message(), message.message());
+        assert_eq!(message_copy.parent_ids().collect::<Vec<_>>(), vec![initial_copy.id()]);
+
+        let refs = transfer.refs(initial_copy.id()).expect("Could not get references");
+        assert_eq!(refs, vec![
+            (format!("refs/dit/{}/head", initial_copy.id()), initial_copy.id()),
+            (format!("refs/dit/{}/leaves/{}", initial_copy.id(), message_copy.id()), message_copy.id()),
+        ]);
+    }
+}
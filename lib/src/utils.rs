@@ -12,6 +12,7 @@
 //! This module provides utilities private to this library.
 //!
 
+use git2::{self, Oid};
 use std::result::Result as RResult;
 
 
@@ -42,3 +43,26 @@ impl<I, E, J> ResultIterExt<I, E> for J
     }
 }
 
+
+/// Resolve the object id stated by a word, e.g. in a trailer's value
+///
+/// Only full hexadecimal object ids and unambiguous abbreviations of at least
+/// four digits are accepted. In particular, symbolic names such as branches or
+/// revision expressions are not resolved, since the object referred to by them
+/// changes over time and differs between clones.
+///
+pub fn resolve_object_id<'r>(repo: &'r git2::Repository, word: &str) -> Option<git2::Object<'r>> {
+    if word.len() < 4 || word.len() > 40 || !word.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    if word.len() == 40 {
+        return Oid::from_str(word).ok().and_then(|id| repo.find_object(id, None).ok());
+    }
+
+    // Abbreviated ids are resolved as revisions. A reference with the same
+    // name may shadow the object, hence we check the id of the result.
+    let word = word.to_lowercase();
+    repo.revparse_single(&word)
+        .ok()
+        .filter(|object| object.id().to_string().starts_with(&word))
+}
@@ -0,0 +1,207 @@
+// git-dit - the distributed issue tracker for git
+// Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+// Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+//
+
+//! Cross-repository issue references
+//!
+//! Messages may only refer to commits in the same object database via their
+//! parents. Issues in other repositories may be referenced via `Dit-ref`
+//! trailers of the form `<location>#<issue-id>` instead, where the location is
+//! the name or URL of a remote or the name or path of a sibling repository.
+//! This module provides a type for representing such references as well as a
+//! resolver for looking up the issues referenced.
+//!
+
+use git2::{self, Oid};
+use std::fmt;
+use std::result::Result as RResult;
+use std::str::FromStr;
+
+use issue::Issue;
+use repository::RepositoryExt;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Reference to an issue in another repository
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IssueRef {
+    /// Location of the repository, e.g. the name of a remote
+    pub location: String,
+    /// Id of the issue referenced
+    pub id: Oid,
+}
+
+impl FromStr for IssueRef {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Only the first word of a trailer value is considered.
+        let value = s.split_whitespace().next().unwrap_or_default();
+        let pos = value
+            .rfind('#')
+            .ok_or_else(|| Error::from_kind(EK::MalformedIssueReference(s.to_owned())))?;
+        let location = &value[..pos];
+        if location.is_empty() {
+            return Err(Error::from_kind(EK::MalformedIssueReference(s.to_owned())));
+        }
+        let id = Oid::from_str(&value[pos + 1..])
+            .chain_err(|| EK::MalformedIssueReference(s.to_owned()))?;
+
+        Ok(IssueRef { location: location.to_owned(), id })
+    }
+}
+
+impl fmt::Display for IssueRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> RResult<(), fmt::Error> {
+        write!(f, "{}#{}", self.location, self.id)
+    }
+}
+
+
+/// Resolver for issue references
+///
+/// The resolver looks up issues referenced under the remote references of a
+/// repository, i.e. `refs/remotes/<remote>/dit/`, and in sibling repositories
+/// registered with the resolver.
+///
+pub struct IssueResolver<'r> {
+    repo: &'r git2::Repository,
+    siblings: Vec<(String, git2::Repository)>,
+}
+
+impl<'r> IssueResolver<'r> {
+    /// Create a new resolver, considering only the repository's remotes
+    ///
+    pub fn new(repo: &'r git2::Repository) -> Self {
+        IssueResolver { repo, siblings: Vec::new() }
+    }
+
+    /// Register a sibling repository under a name
+    ///
+    pub fn add_sibling<N: Into<String>>(&mut self, name: N, sibling: git2::Repository) {
+        self.siblings.push((name.into(), sibling));
+    }
+
+    /// Resolve an issue reference
+    ///
+    /// The location of the reference is matched against the names and URLs of
+    /// the repository's remotes as well as the names and paths of the sibling
+    /// repositories, in this order. If the issue could not be found in the
+    /// repository matching the location, `None` is returned.
+    ///
+    pub fn resolve(&self, reference: &IssueRef) -> Result<Option<Issue<'_>>> {
+        if let Some(remote) = self.matching_remote(&reference.location)? {
+            let head = format!("refs/remotes/{}/dit/{}/head", remote, reference.id);
+            return Ok(match self.repo.find_reference(&head) {
+                Ok(_) => Some(Issue::new(self.repo, reference.id)?),
+                Err(_) => None,
+            });
+        }
+
+        let location = reference.location.trim_end_matches('/');
+        let sibling = self
+            .siblings
+            .iter()
+            .find(|&(name, repo)| name == location || Self::is_located_at(repo, location));
+        Ok(sibling.and_then(|(_, repo)| repo.find_issue(reference.id).ok()))
+    }
+
+    /// Find the name of the remote matching a location
+    ///
+    fn matching_remote(&self, location: &str) -> Result<Option<String>> {
+        let remotes = self
+            .repo
+            .remotes()
+            .chain_err(|| EK::CannotGetRemotes)?;
+        for name in remotes.iter().flatten() {
+            let remote = self
+                .repo
+                .find_remote(name)
+                .chain_err(|| EK::CannotGetRemotes)?;
+            if name == location || remote.url() == Some(location) {
+                return Ok(Some(name.to_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check whether a repository is located at a specific path
+    ///
+    fn is_located_at(repo: &git2::Repository, location: &str) -> bool {
+        Some(repo.path())
+            .into_iter()
+            .chain(repo.workdir())
+            .any(|path| path.to_string_lossy().trim_end_matches('/') == location)
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_utils::TestingRepo;
+
+    // IssueRef tests
+
+    #[test]
+    fn parse_issue_ref() {
+        let reference: IssueRef = "origin#a0b1c2d3e4f5a0b1c2d3e4f5a0b1c2d3e4f5a0b1 foo"
+            .parse()
+            .expect("Could not parse issue reference");
+        assert_eq!(reference.location, "origin");
+        assert_eq!(reference.id.to_string(), "a0b1c2d3e4f5a0b1c2d3e4f5a0b1c2d3e4f5a0b1");
+        assert_eq!(reference.to_string(), "origin#a0b1c2d3e4f5a0b1c2d3e4f5a0b1c2d3e4f5a0b1");
+
+        assert!("origin".parse::<IssueRef>().is_err());
+        assert!("#a0b1c2d3e4f5a0b1c2d3e4f5a0b1c2d3e4f5a0b1".parse::<IssueRef>().is_err());
+        assert!("origin#foo".parse::<IssueRef>().is_err());
+    }
+
+    // IssueResolver tests
+
+    #[test]
+    fn resolve_issue_ref() {
+        let mut testing_repo = TestingRepo::new("resolve_issue_ref");
+        let repo = testing_repo.repo();
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+
+        let issue = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        repo.remote("origin", "https://example.com/foo.git")
+            .expect("Could not create remote");
+        repo.reference(
+            &format!("refs/remotes/origin/dit/{}/head", issue.id()),
+            issue.id(),
+            false,
+            "create remote head"
+        ).expect("Could not create reference");
+
+        let resolver = IssueResolver::new(repo);
+        let resolve = |value: &str| resolver
+            .resolve(&value.parse().expect("Could not parse issue reference"))
+            .expect("Could not resolve issue reference")
+            .map(|issue| issue.id());
+
+        let id = issue.id();
+        assert_eq!(resolve(&format!("origin#{}", id)), Some(id));
+        assert_eq!(resolve(&format!("https://example.com/foo.git#{}", id)), Some(id));
+        assert_eq!(resolve(&format!("origin#{}", empty_tree.id())), None);
+        assert_eq!(resolve(&format!("upstream#{}", id)), None);
+    }
+}
@@ -0,0 +1,516 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Shell completion
+//!
+//! Completion scripts are generated from the command line specification and
+//! extended with shell specific functions completing values which depend on
+//! the repository: the ids of issues and the keys of trailers. Those values are
+//! retrieved at completion time via the `get-completion-candidates` plumbing
+//! command, which prints one candidate per line, followed by a tab and a
+//! description.
+//!
+
+use clap::{App, Shell};
+use git2::Repository;
+use libgitdit::RepositoryExt;
+use libgitdit::template::Template;
+use libgitdit::trailer::spec;
+use std::collections::HashSet;
+use std::fs;
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Name of the binary completed
+///
+const BIN_NAME: &str = "git-dit";
+
+/// Subcommands taking an issue as a positional argument
+///
+/// The parent message of `reply` is completed like an issue.
+///
+const ISSUE_COMMANDS: &[&str] = &["show", "reply", "tag", "gc", "push", "fetch"];
+
+/// Subcommands taking a remote as the positional argument preceding the issue
+///
+const REMOTE_COMMANDS: &[&str] = &["push", "fetch"];
+
+/// Options taking a trailer, as tuples of subcommand, short and long option
+///
+const TRAILER_OPTIONS: &[(&str, &str, &str)] = &[
+    ("new", "M", "metadata"),
+    ("split", "M", "metadata"),
+    ("tag", "s", "status"),
+];
+
+
+/// Get the candidates for completing an issue
+///
+/// Issues are described by their subject.
+///
+pub fn issue_candidates(repo: &Repository) -> Result<Vec<(String, String)>> {
+    let mut retval = Vec::new();
+    for issue in repo.issues()? {
+        let subject = issue
+            .initial_message()?
+            .summary()
+            .map(String::from)
+            .unwrap_or_default();
+        retval.push((issue.id().to_string(), subject));
+    }
+    retval.sort();
+    Ok(retval)
+}
+
+
+/// Get the candidates for completing a trailer key
+///
+/// The candidates consist of the keys of the trailers known to dit and the
+/// keys of trailers required by the templates configured.
+///
+pub fn trailer_key_candidates(repo: &Repository) -> Result<Vec<(String, String)>> {
+    let mut retval: Vec<(String, String)> = spec::KNOWN_SPECS
+        .iter()
+        .map(|spec| (spec.key.to_owned(), "Known to dit".to_owned()))
+        .collect();
+
+    let mut known: HashSet<String> = retval.iter().map(|(key, _)| key.clone()).collect();
+    for (name, template) in templates(repo)? {
+        for key in template.required_trailers() {
+            if known.insert(key.clone()) {
+                retval.push((key.clone(), format!("Required by template '{}'", name)));
+            }
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Get the templates configured, along with their names
+///
+/// Templates configured via `dit.template.<name>` take precedence over those
+/// in the `.dit/templates/` directory. Templates which cannot be read are
+/// ignored.
+///
+fn templates(repo: &Repository) -> Result<Vec<(String, Template)>> {
+    let config = repo.config()?;
+    let mut paths = Vec::new();
+    for entry in &config.entries(Some(r"^dit\.template\."))? {
+        if let Some(name) = entry?.name() {
+            if let Ok(path) = config.get_path(name) {
+                paths.push((name["dit.template.".len()..].to_owned(), path));
+            }
+        }
+    }
+    if let Some(dir) = repo.workdir().map(|dir| dir.join(".dit").join("templates")) {
+        if dir.is_dir() {
+            for entry in fs::read_dir(dir).chain_err(|| EK::WrappedIOError)? {
+                let path = entry.chain_err(|| EK::WrappedIOError)?.path();
+                let name = match (path.file_stem(), path.extension()) {
+                    (Some(stem), Some(ext)) if ext == "md" => stem.to_string_lossy().into_owned(),
+                    _ => continue,
+                };
+                if !paths.iter().any(|(known, _)| *known == name) {
+                    paths.push((name, path));
+                }
+            }
+        }
+    }
+
+    Ok(paths
+        .into_iter()
+        .filter_map(|(name, path)| fs::read_to_string(path)
+            .ok()
+            .map(|text| (name, Template::from_lines(text.lines())))
+        )
+        .collect())
+}
+
+
+/// Generate the completion script for a shell
+///
+/// An error is returned if the script generated does not have the shape
+/// expected, i.e. if it could not be extended.
+///
+pub fn script(mut app: App, shell: Shell) -> Result<String> {
+    let mut generated = Vec::new();
+    app.gen_completions_to(BIN_NAME, shell, &mut generated);
+    let generated = String::from_utf8_lossy(&generated);
+
+    match shell {
+        Shell::Bash => bash_script(&generated),
+        Shell::Zsh => zsh_script(&generated),
+        Shell::Fish => fish_script(&generated),
+        _ => Ok(generated.into_owned()),
+    }
+}
+
+
+/// Replace all occurrences of a pattern, which must occur at least once
+///
+fn replace_existing(text: &str, from: &str, to: &str) -> Result<String> {
+    if !text.contains(from) {
+        return Err(Error::from_kind(EK::CannotExtendCompletion(from.to_owned())));
+    }
+    Ok(text.replace(from, to))
+}
+
+
+/// Extend a generated bash completion script
+///
+/// The function generated is wrapped by one completing issues and trailer keys.
+/// For `git dit`, the function `_git_dit` is provided, which git's completion
+/// will pick up.
+///
+fn bash_script(generated: &str) -> Result<String> {
+    // The generated script uses inconsistent names for the binary's state.
+    let generated = replace_existing(generated, "cmd=\"git-dit\"", "cmd=\"git__dit\"")?;
+    let generated = replace_existing(&generated, "\n        git-dit)\n", "\n        git__dit)\n")?;
+    let generated = replace_existing(&generated, "complete -F _git-dit ", "complete -F _git-dit_dynamic ")?;
+
+    // Values of other options are completed by the generated function.
+    let mut value_options = Vec::new();
+    for command in ISSUE_COMMANDS {
+        let options = bash_value_options(&generated, command)
+            .ok_or_else(|| Error::from_kind(EK::CannotExtendCompletion(format!("git__dit__{}", command))))?;
+        value_options.extend(options.into_iter().map(|option| format!("{}:{}", command, option)));
+    }
+    let trailer_options: Vec<String> = TRAILER_OPTIONS
+        .iter()
+        .flat_map(|&(command, short, long)| vec![
+            format!("{}:-{}", command, short),
+            format!("{}:--{}", command, long),
+        ])
+        .collect();
+
+    let mut arms = format!(
+        "        {})\n            compopt -o nospace\n            \
+         COMPREPLY=( $(compgen -S '=' -W \"$({} get-completion-candidates trailer-keys 2>/dev/null | cut -f1)\" -- \"${{cur}}\") )\n            \
+         return 0\n            ;;\n",
+        trailer_options.join("|"),
+        BIN_NAME,
+    );
+    if !value_options.is_empty() {
+        arms.push_str(&format!(
+            "        {})\n            _git-dit\n            return 0\n            ;;\n",
+            value_options.join("|"),
+        ));
+    }
+
+    Ok(generated + &BASH_GLUE
+        .replace("@BIN@", BIN_NAME)
+        .replace("@OPTION_ARMS@", &arms)
+        .replace("@ISSUE_COMMANDS@", &ISSUE_COMMANDS.join("|"))
+        .replace("@REMOTE_COMMANDS@", &REMOTE_COMMANDS.join("|")))
+}
+
+
+/// Get the options of a subcommand taking a value from a generated bash script
+///
+/// `None` is returned if the script has no section for the subcommand.
+///
+fn bash_value_options(generated: &str, command: &str) -> Option<Vec<String>> {
+    let label = format!("        git__dit__{})", command.replace('-', "__"));
+    let mut lines = generated.lines().skip_while(|line| *line != label);
+    lines.next()?;
+    Some(lines
+        .take_while(|line| *line != "            ;;")
+        .map(str::trim)
+        .filter(|line| line.starts_with('-') && line.ends_with(')'))
+        .map(|line| line.trim_end_matches(')').to_owned())
+        .collect())
+}
+
+
+/// Shell functions completing issues and trailer keys in bash
+///
+const BASH_GLUE: &str = r#"
+_git-dit_dynamic() {
+    local cur prev word sub i positionals
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    sub=""
+    positionals=0
+
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        word="${COMP_WORDS[i]}"
+        case "${word}" in
+            --format)
+                [[ -z "${sub}" ]] && ((i++))
+                ;;
+            -*)
+                ;;
+            *)
+                if [[ -z "${sub}" ]]; then
+                    sub="${word}"
+                else
+                    ((positionals++))
+                fi
+                ;;
+        esac
+    done
+
+    case "${sub}:${prev}" in
+@OPTION_ARMS@    esac
+
+    if [[ "${cur}" != -* ]]; then
+        case "${sub}" in
+            @REMOTE_COMMANDS@)
+                if [[ ${positionals} -eq 0 ]]; then
+                    _git-dit
+                    return 0
+                fi
+                ;;
+        esac
+        case "${sub}" in
+            @ISSUE_COMMANDS@)
+                COMPREPLY=( $(compgen -W "$(@BIN@ get-completion-candidates issues 2>/dev/null | cut -f1)" -- "${cur}") )
+                return 0
+                ;;
+        esac
+    fi
+
+    _git-dit
+}
+
+_git_dit() {
+    local COMP_WORDS=("@BIN@" "${COMP_WORDS[@]:2}")
+    local COMP_CWORD=$((COMP_CWORD - 1))
+    _git-dit_dynamic
+}
+"#;
+
+
+/// Extend a generated zsh completion script
+///
+/// Issue arguments and trailer options are completed via additional functions.
+/// The function `_git-dit` is also used by zsh's git completion for `git dit`.
+/// An error is returned if any of the arguments or options to patch was not
+/// found.
+///
+fn zsh_script(generated: &str) -> Result<String> {
+    let mut retval = String::new();
+    let mut patched = HashSet::new();
+    let mut has_glue = false;
+    let mut section: Option<String> = None;
+    let mut entry: Option<String> = None;
+    for line in generated.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('(') && trimmed.ends_with(')') && !trimmed.contains(' ') {
+            section = Some(trimmed[1..trimmed.len() - 1].to_owned());
+        } else if trimmed == ";;" {
+            section = None;
+        }
+        if entry.is_none() && line.starts_with('\'') {
+            entry = Some(line.to_owned());
+        }
+
+        let mut line = line.to_owned();
+        if line.ends_with("' \\") {
+            if let (Some(command), Some(ref first)) = (section.as_ref(), entry.take()) {
+                if let Some(patch) = zsh_entry_end(command, first, &line) {
+                    patched.insert(patch.0);
+                    line = patch.1;
+                }
+            }
+        }
+        if line == format!("_{} \"$@\"", BIN_NAME) {
+            retval.push_str(ZSH_GLUE);
+            has_glue = true;
+        }
+        retval.push_str(&line);
+        retval.push('\n');
+    }
+
+    if !has_glue {
+        return Err(Error::from_kind(EK::CannotExtendCompletion(format!("_{} \"$@\"", BIN_NAME))));
+    }
+    let expected = ISSUE_COMMANDS
+        .iter()
+        .map(|command| format!("{} issue", command))
+        .chain(TRAILER_OPTIONS.iter().flat_map(|&(command, short, long)| vec![
+            format!("{} -{}", command, short),
+            format!("{} --{}", command, long),
+        ]));
+    for spec in expected {
+        if !patched.contains(&spec) {
+            return Err(Error::from_kind(EK::CannotExtendCompletion(spec)));
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Patch the last line of an argument specification in a generated zsh script
+///
+/// If the specification is patched, the patched line is returned along with a
+/// description of the argument, consisting of the subcommand and either
+/// "issue" or the option.
+///
+fn zsh_entry_end(command: &str, first: &str, line: &str) -> Option<(String, String)> {
+    let is_issue = ["issue", "parent"]
+        .iter()
+        .any(|name| first.starts_with(&format!("':{} -- ", name)) || first.starts_with(&format!("'::{} -- ", name)));
+    if is_issue && ISSUE_COMMANDS.contains(&command) && line.ends_with(":_files' \\") {
+        let patched = line.trim_end_matches(":_files' \\").to_owned() + ":_git-dit_issues' \\";
+        return Some((format!("{} issue", command), patched));
+    }
+
+    let option = TRAILER_OPTIONS
+        .iter()
+        .filter(|&&(cmd, _, _)| cmd == command)
+        .flat_map(|&(_, short, long)| vec![
+            (format!("'*-{}+[", short), format!("-{}", short)),
+            (format!("'*--{}=[", long), format!("--{}", long)),
+        ])
+        .find(|(prefix, _)| first.starts_with(prefix.as_str()))
+        .map(|(_, option)| option);
+    match option {
+        Some(option) if line.ends_with("]' \\") => {
+            let patched = line.trim_end_matches("' \\").to_owned() + ":trailer:_git-dit_trailer_keys' \\";
+            Some((format!("{} {}", command, option), patched))
+        },
+        _ => None,
+    }
+}
+
+
+/// Shell functions completing issues and trailer keys in zsh
+///
+const ZSH_GLUE: &str = r#"(( $+functions[_git-dit_issues] )) ||
+_git-dit_issues() {
+    local -a issues
+    issues=(${(f)"$(git-dit get-completion-candidates issues 2>/dev/null | tr '\t' ':')"})
+    _describe -t issues 'issue' issues "$@"
+}
+
+(( $+functions[_git-dit_trailer_keys] )) ||
+_git-dit_trailer_keys() {
+    local -a keys
+    keys=(${(f)"$(git-dit get-completion-candidates trailer-keys 2>/dev/null | tr '\t' ':')"})
+    _describe -t trailer-keys 'trailer key' keys -S '=' "$@"
+}
+
+"#;
+
+
+/// Extend a generated fish completion script
+///
+/// For `git dit`, fish's git completion delegates to the completion of
+/// `git-dit`. An error is returned if any of the subcommands or options
+/// completed additionally is not part of the generated script.
+///
+fn fish_script(generated: &str) -> Result<String> {
+    for command in ISSUE_COMMANDS {
+        let condition = format!("-n \"__fish_seen_subcommand_from {}\"", command);
+        if !generated.contains(&condition) {
+            return Err(Error::from_kind(EK::CannotExtendCompletion(condition)));
+        }
+    }
+    for &(command, short, long) in TRAILER_OPTIONS {
+        let option = format!("-n \"__fish_seen_subcommand_from {}\" -s {} -l {} ", command, short, long);
+        if !generated.contains(&option) {
+            return Err(Error::from_kind(EK::CannotExtendCompletion(option)));
+        }
+    }
+
+    let mut retval = generated.to_owned();
+    retval.push_str(FISH_GLUE);
+
+    let others: Vec<&str> = ISSUE_COMMANDS
+        .iter()
+        .filter(|command| !REMOTE_COMMANDS.contains(command))
+        .cloned()
+        .collect();
+    retval.push_str(&format!(
+        "complete -c {0} -n \"__fish_seen_subcommand_from {1}\" -f -a \"({0} get-completion-candidates issues 2>/dev/null)\"\n",
+        BIN_NAME,
+        others.join(" "),
+    ));
+    retval.push_str(&format!(
+        "complete -c {0} -n \"__fish_seen_subcommand_from {1}; and test (__fish_git_dit_positionals) -ge 1\" -f -a \"({0} get-completion-candidates issues 2>/dev/null)\"\n",
+        BIN_NAME,
+        REMOTE_COMMANDS.join(" "),
+    ));
+    for &(command, short, long) in TRAILER_OPTIONS {
+        retval.push_str(&format!(
+            "complete -c {0} -n \"__fish_seen_subcommand_from {1}\" -s {2} -l {3} -x -a \"({0} get-completion-candidates trailer-keys 2>/dev/null | string replace -r '\\t' '=\\t')\"\n",
+            BIN_NAME,
+            command,
+            short,
+            long,
+        ));
+    }
+    Ok(retval)
+}
+
+
+/// Shell functions used for completing issues in fish
+///
+const FISH_GLUE: &str = r#"
+function __fish_git_dit_positionals
+    set -l count -1
+    for word in (commandline -opc)[2..-1]
+        switch $word
+            case '-*'
+            case '*'
+                set count (math $count + 1)
+        end
+    end
+    echo $count
+end
+
+"#;
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Completion tests
+
+    fn generated(shell: Shell) -> String {
+        let mut generated = Vec::new();
+        App::from_yaml(&::cli_yaml()).gen_completions_to(BIN_NAME, shell, &mut generated);
+        String::from_utf8(generated).expect("Generated non-UTF-8 script")
+    }
+
+    #[test]
+    fn bash_completion() {
+        let script = bash_script(&generated(Shell::Bash)).expect("Could not extend script");
+        assert!(!script.contains("cmd=\"git-dit\""));
+        assert!(script.contains("complete -F _git-dit_dynamic "));
+        assert!(script.contains("tag:-s|tag:--status"));
+        assert!(!script.contains("@BIN@"));
+
+        assert!(bash_script("").is_err());
+    }
+
+    #[test]
+    fn zsh_completion() {
+        let script = zsh_script(&generated(Shell::Zsh)).expect("Could not extend script");
+        assert!(script.contains("_git-dit_issues() {"));
+        assert!(script.contains("':issue -- Issue to show:_git-dit_issues' \\"));
+        assert!(script.contains("'*--status=[Set a tag. Key-value pair expected.]:trailer:_git-dit_trailer_keys' \\"));
+
+        assert!(zsh_script("").is_err());
+    }
+
+    #[test]
+    fn fish_completion() {
+        let script = fish_script(&generated(Shell::Fish)).expect("Could not extend script");
+        assert!(script.contains("function __fish_git_dit_positionals"));
+
+        assert!(fish_script("").is_err());
+    }
+}
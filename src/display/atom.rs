@@ -0,0 +1,132 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Atom output facility
+//!
+//! This module provides a minimal representation of Atom feeds (RFC 4287)
+//! with entries carrying plain text content.
+//!
+
+use chrono::DateTime;
+use git2::Commit;
+use std::fmt;
+
+use super::markup::{escape, rfc3339};
+
+
+/// Entry of an Atom feed
+///
+pub struct AtomEntry {
+    id: String,
+    title: String,
+    author_name: String,
+    author_email: String,
+    published: String,
+    updated: String,
+    content: String,
+}
+
+impl AtomEntry {
+    /// Create an entry for a message
+    ///
+    /// The entry's id is derived from the message's id. The message's author
+    /// time is used as the time of publication and the commit time as the time
+    /// of the last update.
+    ///
+    pub fn from_message(message: &Commit) -> Self {
+        let author = message.author();
+        AtomEntry {
+            id: format!("urn:sha1:{}", message.id()),
+            title: message.summary().unwrap_or_default().to_owned(),
+            author_name: author.name().unwrap_or_default().to_owned(),
+            author_email: author.email().unwrap_or_default().to_owned(),
+            published: rfc3339(author.when()),
+            updated: rfc3339(message.time()),
+            content: message.message().unwrap_or_default().trim().to_owned(),
+        }
+    }
+
+    /// Get the time of the last update
+    ///
+    pub fn updated(&self) -> &str {
+        self.updated.as_ref()
+    }
+}
+
+
+/// Atom feed
+///
+/// Entries are rendered in the order in which they were added.
+///
+pub struct AtomFeed {
+    id: String,
+    title: String,
+    link: Option<String>,
+    entries: Vec<AtomEntry>,
+}
+
+impl AtomFeed {
+    /// Create a new, empty feed
+    ///
+    pub fn new<I, T>(id: I, title: T) -> Self
+        where I: Into<String>,
+              T: Into<String>
+    {
+        AtomFeed { id: id.into(), title: title.into(), link: None, entries: Vec::new() }
+    }
+
+    /// Set the link to the resource the feed is associated with
+    ///
+    pub fn link<L: Into<String>>(&mut self, link: L) {
+        self.link = Some(link.into());
+    }
+
+    /// Add an entry
+    ///
+    pub fn entry(&mut self, entry: AtomEntry) {
+        self.entries.push(entry);
+    }
+}
+
+impl fmt::Display for AtomFeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        // The feed was last updated when the most recent entry was.
+        let updated = self
+            .entries
+            .iter()
+            .map(AtomEntry::updated)
+            .max_by_key(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(String::from)
+            .unwrap_or_else(|| rfc3339(git2::Time::new(0, 0)));
+
+        writeln!(f, "<?xml version=\"1.0\" encoding=\"utf-8\"?>")?;
+        writeln!(f, "<feed xmlns=\"http://www.w3.org/2005/Atom\">")?;
+        writeln!(f, "  <id>{}</id>", escape(&self.id))?;
+        writeln!(f, "  <title>{}</title>", escape(&self.title))?;
+        writeln!(f, "  <updated>{}</updated>", updated)?;
+        if let Some(ref link) = self.link {
+            writeln!(f, "  <link href=\"{}\"/>", escape(link))?;
+        }
+        writeln!(f, "  <generator>git-dit</generator>")?;
+        for entry in self.entries.iter() {
+            writeln!(f, "  <entry>")?;
+            writeln!(f, "    <id>{}</id>", escape(&entry.id))?;
+            writeln!(f, "    <title>{}</title>", escape(&entry.title))?;
+            writeln!(f, "    <author>")?;
+            writeln!(f, "      <name>{}</name>", escape(&entry.author_name))?;
+            writeln!(f, "      <email>{}</email>", escape(&entry.author_email))?;
+            writeln!(f, "    </author>")?;
+            writeln!(f, "    <published>{}</published>", entry.published)?;
+            writeln!(f, "    <updated>{}</updated>", entry.updated)?;
+            writeln!(f, "    <content type=\"text\">{}</content>", escape(&entry.content))?;
+            writeln!(f, "  </entry>")?;
+        }
+        write!(f, "</feed>")
+    }
+}
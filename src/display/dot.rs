@@ -0,0 +1,114 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Graphviz output facility
+//!
+//! This module provides a minimal representation of directed graphs which may
+//! be rendered in the DOT language understood by Graphviz.
+//!
+
+use std::fmt;
+
+
+/// List of attributes of a node or an edge
+///
+pub type DotAttributes = Vec<(&'static str, String)>;
+
+
+/// Directed graph in the DOT language
+///
+/// Nodes and edges are rendered in the order in which they were added. Each
+/// node and edge may carry arbitrary attributes, e.g. "style" or "shape".
+///
+pub struct DotGraph {
+    name: String,
+    nodes: Vec<(String, DotAttributes)>,
+    edges: Vec<(String, String, DotAttributes)>,
+}
+
+impl DotGraph {
+    /// Create a new, empty graph
+    ///
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        DotGraph { name: name.into(), nodes: Vec::new(), edges: Vec::new() }
+    }
+
+    /// Add a node, unless a node with the id already exists
+    ///
+    pub fn node<I: Into<String>>(&mut self, id: I, attrs: DotAttributes) {
+        let id = id.into();
+        if !self.has_node(&id) {
+            self.nodes.push((id, attrs));
+        }
+    }
+
+    /// Check whether a node with a specific id exists
+    ///
+    pub fn has_node(&self, id: &str) -> bool {
+        self.nodes.iter().any(|node| node.0 == id)
+    }
+
+    /// Add an edge
+    ///
+    pub fn edge<I, J>(&mut self, from: I, to: J, attrs: DotAttributes)
+        where I: Into<String>,
+              J: Into<String>
+    {
+        self.edges.push((from.into(), to.into(), attrs));
+    }
+}
+
+impl fmt::Display for DotGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "digraph {} {{", quote(&self.name))?;
+        for (id, attrs) in self.nodes.iter() {
+            writeln!(f, "    {}{};", quote(id), Attributes(attrs))?;
+        }
+        for (from, to, attrs) in self.edges.iter() {
+            writeln!(f, "    {} -> {}{};", quote(from), quote(to), Attributes(attrs))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+
+/// Helper for rendering an attribute list
+///
+struct Attributes<'a>(&'a [(&'static str, String)]);
+
+impl<'a> fmt::Display for Attributes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let attrs: Vec<String> = self.0
+            .iter()
+            .map(|&(key, ref value)| format!("{}={}", key, quote(value)))
+            .collect();
+        write!(f, " [{}]", attrs.join(", "))
+    }
+}
+
+
+/// Quote a string as a DOT identifier
+///
+fn quote(s: &str) -> String {
+    let mut retval = String::with_capacity(s.len() + 2);
+    retval.push('"');
+    for c in s.chars() {
+        match c {
+            '"'  => retval.push_str("\\\""),
+            '\\' => retval.push_str("\\\\"),
+            '\n' => retval.push_str("\\n"),
+            c    => retval.push(c),
+        }
+    }
+    retval.push('"');
+    retval
+}
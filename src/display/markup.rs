@@ -0,0 +1,49 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Markup utilities
+//!
+//! This module provides utilities shared by the HTML and XML outputs.
+//!
+
+use chrono::{FixedOffset, TimeZone};
+use git2;
+
+
+/// Format a git time as RFC 3339
+///
+pub fn rfc3339(time: git2::Time) -> String {
+    FixedOffset::east_opt(time.offset_minutes() * 60)
+        .and_then(|offset| offset.timestamp_opt(time.seconds(), 0).single())
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+
+/// Escape text for inclusion in HTML or XML
+///
+/// Characters which may not appear in XML documents, e.g. most control
+/// characters, are dropped.
+///
+pub fn escape(text: &str) -> String {
+    let mut retval = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&'                     => retval.push_str("&amp;"),
+            '<'                     => retval.push_str("&lt;"),
+            '>'                     => retval.push_str("&gt;"),
+            '"'                     => retval.push_str("&quot;"),
+            '\t' | '\n' | '\r'      => retval.push(c),
+            '\u{fffe}' | '\u{ffff}' => {},
+            c if c < ' '            => {},
+            c                       => retval.push(c),
+        }
+    }
+    retval
+}
@@ -15,6 +15,8 @@ use git2::{Commit, Oid};
 use libgitdit::Message;
 use libgitdit::message::block::Block;
 use libgitdit::trailer::spec::TrailerSpec;
+use std::collections::HashSet;
+use std::rc::Rc;
 
 use error::*;
 use super::formatter::{TokenExpander, FormattingToken, LineTokens};
@@ -35,6 +37,7 @@ pub enum MessageFmtToken<'a> {
     Trailers,
     Trailer(TrailerSpec<'a>),
     IfId(Oid, Vec<FormattingToken<MessageFmtToken<'a>, Commit<'a>>>),
+    IfIdIn(Rc<HashSet<Oid>>, Vec<FormattingToken<MessageFmtToken<'a>, Commit<'a>>>),
 }
 
 impl<'a,> TokenExpander for MessageFmtToken<'a> {
@@ -101,6 +104,11 @@ impl<'a,> TokenExpander for MessageFmtToken<'a> {
             } else {
                 Vec::new()
             },
+            &MessageFmtToken::IfIdIn(ref ids, ref tokens) => if ids.contains(&message.id()) {
+                tokens.clone()
+            } else {
+                Vec::new()
+            },
         })
     }
 }
@@ -9,10 +9,17 @@
 
 #[macro_use] mod formatter;
 
+mod atom;
+mod dot;
+mod markup;
 mod message;
 mod msgtree;
 
+pub use self::atom::*;
+pub use self::dot::*;
 pub use self::formatter::*;
+#[cfg(feature = "serve")]
+pub use self::markup::*;
 pub use self::message::*;
 pub use self::msgtree::*;
 
@@ -32,11 +32,56 @@ error_chain! {
             display("Malformed date: {}", date)
         }
 
+        MalformedAge(age: String) {
+            description("Malformed age (expected a number followed by 'd', 'w', 'm' or 'y')")
+            display("Malformed age: {}", age)
+        }
+
+        UnknownTemplate(name: String) {
+            description("Unknown or unreadable message template")
+            display("Unknown or unreadable message template: {}", name)
+        }
+
+        UnknownHeadPolicy(policy: String) {
+            description("Unknown head collection policy or remote")
+            display("Unknown head collection policy or remote: {}", policy)
+        }
+
         UnknownMetadataKey(key: String) {
             description("Unknown metadata key")
             display("Unknown metadata key: {}", key)
         }
 
+        CannotAttachFile(path: String) {
+            description("Cannot attach file")
+            display("Cannot attach file: {}", path)
+        }
+
+        DuplicateAttachment(name: String) {
+            description("Multiple files attached under the same name")
+            display("Multiple files attached under the same name: {}", name)
+        }
+
+        CannotExtractAttachment(name: String) {
+            description("Cannot extract attachment")
+            display("Cannot extract attachment: {}", name)
+        }
+
+        CannotStartServer(reason: String) {
+            description("Cannot start server")
+            display("Cannot start server: {}", reason)
+        }
+
+        CannotExtendCompletion(pattern: String) {
+            description("Cannot extend generated completion script")
+            display("Cannot extend generated completion script, not found: {}", pattern)
+        }
+
+        HookRejected(name: String) {
+            description("Rejected by hook")
+            display("Rejected by hook: {}", name)
+        }
+
         WrappedIOError {
             description("IO Error")
             display("IO Error")
@@ -68,6 +68,12 @@ pub struct FilterSpec {
 }
 
 impl FilterSpec {
+    /// Get the key of the metadata to filter
+    ///
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
     fn into_trailer<'a>(self, spec: spec::TrailerSpec<'a>) -> (TrailerFilter<'a>, bool) {
         (TrailerFilter::new(spec, self.matcher), self.negated)
     }
@@ -127,6 +133,22 @@ impl FromStr for FilterSpec {
 }
 
 
+/// Hide issues marked as duplicates unless filtering for a status
+///
+/// A spec excluding issues with the status "duplicate" is added to the specs
+/// supplied unless any of them refers to the status.
+///
+pub fn hide_duplicates(specs: &mut Vec<FilterSpec>) {
+    if !specs.iter().any(|spec| spec.key() == "status") {
+        specs.push(FilterSpec {
+            key: "status".to_owned(),
+            matcher: ValueMatcher::Equals(TrailerValue::from_slice("duplicate")),
+            negated: true,
+        });
+    }
+}
+
+
 /// Metadata filter
 ///
 pub struct MetadataFilter<'a> {
@@ -148,6 +170,8 @@ impl<'a> MetadataFilter<'a> {
             match s.key.as_ref() {
                 "status"            => trailers.push(s.into_trailer(spec::ISSUE_STATUS_SPEC.clone())),
                 "type"              => trailers.push(s.into_trailer(spec::ISSUE_TYPE_SPEC.clone())),
+                "assignee"          => trailers.push(s.into_trailer(spec::ASSIGNEE_SPEC.clone())),
+                "duplicate-of"      => trailers.push(s.into_trailer(spec::DUPLICATE_OF_SPEC.clone())),
                 "reporter-name"     => nontrailers.push(s.into_nontrailer(NonTrailer::ReporterName)),
                 "reporter-email"    => nontrailers.push(s.into_nontrailer(NonTrailer::ReporterEMail)),
                 _                   => return Err(Error::from_kind(EK::UnknownMetadataKey(s.key.to_string()))),
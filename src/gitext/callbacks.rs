@@ -8,6 +8,8 @@
 //
 
 use git2::{self, Cred};
+use libgitdit::changes::RefUpdate;
+use std::cell::RefCell;
 use std::io::{self, Write};
 use std::result::Result as RResult;
 use std::str;
@@ -79,3 +81,40 @@ pub fn callbacks() -> git2::RemoteCallbacks<'static> {
     retval
 }
 
+
+
+/// Callbacks to use for pushes which must not be rejected partially
+///
+/// With these callbacks, a push fails if the remote rejects any of the
+/// references pushed.
+///
+pub fn strict_push_callbacks() -> git2::RemoteCallbacks<'static> {
+    let mut retval = callbacks();
+    retval.push_update_reference(|refname, failmsg| {
+        print_push_ref_updates(refname, failmsg)?;
+        match failmsg {
+            None      => Ok(()),
+            Some(msg) => Err(git2::Error::from_str(&format!("{} was rejected: {}", refname, msg))),
+        }
+    });
+    retval
+}
+
+
+/// Callbacks to use for fetches
+///
+/// Rather than being printed, reference updates are recorded for later
+/// interpretation. Sideband progress is only printed if requested.
+///
+pub fn fetch_callbacks<'a>(updates: &'a RefCell<Vec<RefUpdate>>, progress: bool) -> git2::RemoteCallbacks<'a> {
+    let mut retval = git2::RemoteCallbacks::new();
+    retval.credentials(get_creds);
+    if progress {
+        retval.sideband_progress(print_sideband);
+    }
+    retval.update_tips(move |refname, old, new| {
+        updates.borrow_mut().push(RefUpdate::new(refname, old, new));
+        true
+    });
+    retval
+}
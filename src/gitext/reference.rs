@@ -8,6 +8,7 @@
 //
 
 use git2::Reference;
+use libgitdit::remote::remote_of_ref;
 use std::borrow::Borrow;
 
 
@@ -25,19 +26,7 @@ pub trait ReferrenceExt {
 
 impl<'r> ReferrenceExt for Reference<'r> {
     fn remote(&self) -> Option<&str> {
-        if let Some(name) = self.name() {
-            let mut name_parts = name.split('/');
-
-            if !is_match!(name_parts.next(), Some("refs")) {
-                return None
-            }
-            if !is_match!(name_parts.next(), Some("remotes")) {
-                return None
-            }
-            name_parts.next()
-        } else {
-            None
-        }
+        self.name().and_then(remote_of_ref)
     }
 }
 
@@ -75,6 +64,12 @@ impl RemotePriorization {
             None => Some(0),
         }
     }
+
+    /// Get the names of the remotes, in descending order of priority
+    ///
+    pub fn remotes(&self) -> &[String] {
+        &self.0
+    }
 }
 
 impl<'a> From<&'a str> for RemotePriorization {
@@ -0,0 +1,211 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Client-side hooks
+//!
+//! Like git hooks, dit hooks are executables residing in the repository's
+//! hooks directory, i.e. `.git/hooks/` or the directory configured via
+//! `core.hooksPath`. The following hooks are supported:
+//!
+//!  * `dit-pre-message`: invoked with the path of a temporary file holding the
+//!    text of a message about to be created. The hook may alter the file. A
+//!    non-zero exit status prevents the creation of the message.
+//!  * `dit-post-message`: invoked after a message was created.
+//!  * `dit-pre-head-update`: invoked before the local head reference of an
+//!    issue is updated. A non-zero exit status prevents the update.
+//!  * `dit-post-fetch`: invoked after a fetch with one line of the form
+//!    `<old> <new> <ref>` for each reference updated on its standard input.
+//!
+//! Information about the issue, message and reference involved is passed via
+//! the environment variables `GIT_DIT_ISSUE`, `GIT_DIT_PARENT`,
+//! `GIT_DIT_MESSAGE`, `GIT_DIT_OLD`, `GIT_DIT_REF` and `GIT_DIT_REMOTE`, as
+//! applicable.
+//!
+
+use git2::{Oid, Repository};
+use libgitdit::changes::RefUpdate;
+use libgitdit::message::LineIteratorExt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+
+use error::*;
+use error::ErrorKind as EK;
+
+
+/// Run the `dit-pre-message` hook
+///
+/// Returns the text of the message, possibly altered by the hook. Since the
+/// hook may alter the message arbitrarily, the text is stripped and its format
+/// checked again.
+///
+pub fn pre_message(repo: &Repository, issue: Option<Oid>, parent: Option<Oid>, message: &str) -> Result<String> {
+    let mut hook = match Hook::find(repo, "dit-pre-message")? {
+        Some(hook) => hook,
+        None => return Ok(message.to_owned()),
+    };
+
+    // The file is specific to this invocation and removed afterwards.
+    let path = repo.path().join(format!("DIT_MESSAGE_{}", process::id()));
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(message.as_bytes()))
+        .chain_err(|| EK::WrappedIOError)?;
+    hook.command.arg(&path);
+    if let Some(issue) = issue {
+        hook.command.env("GIT_DIT_ISSUE", issue.to_string());
+    }
+    if let Some(parent) = parent {
+        hook.command.env("GIT_DIT_PARENT", parent.to_string());
+    }
+    let text = hook
+        .run(None)
+        .and_then(|_| fs::read_to_string(&path).chain_err(|| EK::WrappedIOError));
+    if let Err(err) = fs::remove_file(&path) {
+        warn!("Could not remove {}: {}", path.display(), err);
+    }
+
+    let lines: Vec<String> = text?.lines().stripped().collect();
+    lines.iter().check_message_format()?;
+    Ok(lines.into_iter().collect_string())
+}
+
+
+/// Run the `dit-post-message` hook
+///
+pub fn post_message(repo: &Repository, issue: Oid, message: Oid, refname: &str) -> Result<()> {
+    if let Some(mut hook) = Hook::find(repo, "dit-post-message")? {
+        hook.command
+            .env("GIT_DIT_ISSUE", issue.to_string())
+            .env("GIT_DIT_MESSAGE", message.to_string())
+            .env("GIT_DIT_REF", refname);
+        hook.run(None).or_else(ignore_rejection)?;
+    }
+    Ok(())
+}
+
+
+/// Run the `dit-pre-head-update` hook
+///
+/// A zero `old` id denotes a head reference which does not exist yet.
+///
+pub fn pre_head_update(repo: &Repository, issue: Oid, refname: &str, old: Oid, new: Oid) -> Result<()> {
+    if let Some(mut hook) = Hook::find(repo, "dit-pre-head-update")? {
+        hook.command
+            .env("GIT_DIT_ISSUE", issue.to_string())
+            .env("GIT_DIT_MESSAGE", new.to_string())
+            .env("GIT_DIT_OLD", old.to_string())
+            .env("GIT_DIT_REF", refname);
+        hook.run(None)?;
+    }
+    Ok(())
+}
+
+
+/// Run the `dit-post-fetch` hook
+///
+pub fn post_fetch(repo: &Repository, remote: &str, updates: &[RefUpdate]) -> Result<()> {
+    if let Some(mut hook) = Hook::find(repo, "dit-post-fetch")? {
+        let input: String = updates
+            .iter()
+            .map(|update| format!("{} {} {}\n", update.old, update.new, update.refname))
+            .collect();
+        hook.command.env("GIT_DIT_REMOTE", remote);
+        hook.run(Some(input.as_bytes())).or_else(ignore_rejection)?;
+    }
+    Ok(())
+}
+
+
+/// Hook about to be run
+///
+struct Hook {
+    name: &'static str,
+    command: Command,
+}
+
+impl Hook {
+    /// Find a hook by its name
+    ///
+    /// Returns `None` if the hook does not exist.
+    ///
+    fn find(repo: &Repository, name: &'static str) -> Result<Option<Self>> {
+        let dir = repo
+            .config()?
+            .get_path("core.hooksPath")
+            .ok()
+            .map(|path| match repo.workdir() {
+                Some(workdir) if path.is_relative() => workdir.join(path),
+                _ => path,
+            })
+            .unwrap_or_else(|| repo.path().join("hooks"));
+        let path: PathBuf = dir.join(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        // Like git, we run hooks in the root of the working tree.
+        let mut command = Command::new(path);
+        command
+            .current_dir(repo.workdir().unwrap_or_else(|| repo.path()))
+            .env("GIT_DIR", repo.path());
+        Ok(Some(Hook { name, command }))
+    }
+
+    /// Run the hook, optionally passing some input via its standard input
+    ///
+    /// Hooks which are not executable are ignored. A non-zero exit status is
+    /// reported as a rejection.
+    ///
+    fn run(mut self, input: Option<&[u8]>) -> Result<()> {
+        let status = self.command
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .spawn()
+            .and_then(|mut child| {
+                // Hooks may not consume their input. Only the exit status
+                // decides whether the hook succeeded.
+                let written = match (child.stdin.take(), input) {
+                    (Some(mut stdin), Some(input)) => match stdin.write_all(input) {
+                        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                        result => result,
+                    },
+                    _ => Ok(()),
+                };
+                let status = child.wait()?;
+                written.map(|_| status)
+            });
+        match status {
+            Ok(ref status) if status.success() => Ok(()),
+            Ok(_) => Err(Error::from_kind(EK::HookRejected(self.name.to_owned()))),
+            Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                warn!("The '{}' hook was ignored because it is not executable", self.name);
+                Ok(())
+            },
+            Err(err) => Err(err).chain_err(|| EK::WrappedIOError),
+        }
+    }
+}
+
+
+/// Turn a rejection by a hook into a warning
+///
+/// The exit status of hooks run after the fact is only reported.
+///
+fn ignore_rejection(err: Error) -> Result<()> {
+    match *err.kind() {
+        EK::HookRejected(ref name) => {
+            warn!("The '{}' hook failed", name);
+            Ok(())
+        },
+        _ => Err(err),
+    }
+}
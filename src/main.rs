@@ -17,23 +17,34 @@ extern crate chrono;
 extern crate git2;
 extern crate libgitdit;
 extern crate regex;
+#[macro_use] extern crate serde_json;
+#[cfg(feature = "tui")] extern crate termion;
+#[cfg(feature = "serve")] extern crate tiny_http;
+extern crate yaml_rust;
 
 #[macro_use] mod display;
 
+mod completion;
 mod error;
 mod filters;
 mod gitext;
+mod hooks;
+mod notify;
+#[cfg(feature = "serve")] mod serve;
 mod system;
+#[cfg(feature = "tui")] mod tui;
 mod util;
 
 use clap::App;
 use git2::Commit;
 use libgitdit::issue::IssueRefType;
 use libgitdit::message::LineIteratorExt;
+use libgitdit::plugin::OutputFormat;
 use libgitdit::{Message, RepositoryExt};
 use log::Level;
 use std::fs::File;
 use std::io::{self, Read, Write};
+use yaml_rust::Yaml;
 
 use util::{RepositoryUtil};
 use system::{Abortable, IteratorExt, LinesExt};
@@ -99,9 +110,13 @@ fn create_message(matches: &clap::ArgMatches) {
                          .unwrap_or_default();
     let parent_refs = parents.iter().map(|command| command);
 
-    // use the first parent's tree if availible
+    // use the first parent's tree if availible, without its attachments
     let tree = match parents.first() {
-        Some(commit) => commit.tree().unwrap_or_abort(),
+        Some(commit) => libgitdit::attachment::message_tree(
+            &repo,
+            Some(&commit.tree().unwrap_or_abort()),
+            Vec::new()
+        ).unwrap_or_abort(),
         _            => repo.empty_tree().unwrap_or_abort(),
     };
 
@@ -132,6 +147,24 @@ fn find_tree_init_hash(matches: &clap::ArgMatches) {
 }
 
 
+/// get-completion-candidates subcommand implementation
+///
+fn get_completion_candidates(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+
+    // note: the kind is always present since it is a required parameter
+    let candidates = match matches.value_of("kind").unwrap() {
+        "issues" => completion::issue_candidates(&repo),
+        _        => completion::trailer_key_candidates(&repo),
+    }.unwrap_or_abort();
+    candidates
+        .into_iter()
+        .map(|(value, description)| format!("{}\t{}", value, description))
+        .print_lines()
+        .unwrap_or_abort();
+}
+
+
 /// get-issue-metadata subcommand implementation
 ///
 fn get_issue_metadata(matches: &clap::ArgMatches) {
@@ -179,6 +212,448 @@ fn get_issue_tree_init_hashes(_: &clap::ArgMatches) {
 
 // Porcelain subcommand implementations
 
+/// archive subcommand implementation
+///
+fn archive_impl(matches: &clap::ArgMatches) {
+    use filters::MetadataFilter;
+
+    let repo = util::open_dit_repo();
+    let remote_prios = repo.remote_priorization();
+
+    let filter = match matches.values_of("filter") {
+        Some(values) => {
+            let specs = values.map(str::parse).abort_on_err();
+            MetadataFilter::new(&remote_prios, specs).unwrap_or_abort()
+        },
+        None         => MetadataFilter::empty(&remote_prios),
+    };
+
+    // Issues with messages newer than the threshold are considered active.
+    let threshold = matches
+        .value_of("older-than")
+        .map(|age| chrono::Utc::now().timestamp() - util::parse_age(age).unwrap_or_abort());
+
+    let committer = repo.signature().unwrap_or_abort();
+
+    repo.cli_issues(matches)
+        .unwrap_or_else(|| repo.issues().unwrap_or_abort())
+        .into_iter()
+        .filter(|issue| filter.filter(issue))
+        .filter(|issue| threshold.map(|threshold| issue
+            .messages()
+            .unwrap_or_abort()
+            .abort_on_err()
+            .all(|message| message.time().seconds() < threshold)
+        ).unwrap_or(true))
+        .map(|issue| {
+            if !matches.is_present("dry-run") {
+                issue.archive(&committer).unwrap_or_abort();
+            }
+            issue.id().to_string()
+        })
+        .print_lines()
+        .unwrap_or_abort();
+}
+
+
+/// attachments subcommand implementation
+///
+fn attachments_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+
+    // NOTE: the message is a required parameter
+    let message = repo.value_to_commit(matches.value_of("message").unwrap());
+    let names: Option<Vec<&str>> = matches.values_of("name").map(Iterator::collect);
+
+    let attachments = libgitdit::attachment::attachments(&repo, &message)
+        .unwrap_or_abort()
+        .into_iter()
+        .filter(|attachment| names
+            .as_ref()
+            .map(|names| names.contains(&attachment.name()))
+            .unwrap_or(true)
+        );
+
+    if matches.is_present("extract") {
+        let dir = std::path::Path::new(matches.value_of("output").unwrap_or("."));
+        let force = matches.is_present("force");
+        for attachment in attachments {
+            util::extract_attachment(dir, &attachment, force).unwrap_or_abort();
+            println!("[dit][attachments] {}", attachment.name());
+        }
+    } else {
+        attachments
+            .map(|attachment| format!("{} {}", attachment.name(), attachment.size()))
+            .print_lines()
+            .unwrap_or_abort();
+    }
+}
+
+
+/// changelog subcommand implementation
+///
+fn changelog_impl(matches: &clap::ArgMatches) {
+    use libgitdit::iter::MessagesExt;
+    use libgitdit::trailer::spec::ISSUE_TYPE_SPEC;
+    use std::collections::{BTreeMap, HashMap};
+
+    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
+    use gitext::ReferrencesExt;
+
+    let repo = util::open_dit_repo();
+    let prios = repo.remote_priorization();
+    let id_len = repo.abbreviation_length(matches);
+
+    // NOTE: the range is a required parameter
+    let (from, to) = {
+        let range = repo
+            .revparse(matches.value_of("range").unwrap())
+            .unwrap_or_abort();
+        let commit_id = |object: &git2::Object| object
+            .peel(git2::ObjectType::Commit)
+            .unwrap_or_abort()
+            .id();
+        let from = range.from().map(&commit_id);
+        match range.to() {
+            Some(to) => (from, commit_id(to)),
+            None => (None, from.unwrap()), // a single revision was supplied
+        }
+    };
+
+    // find the issues fixed in the range
+    let tracker = repo.fix_tracker().unwrap_or_abort();
+    let mut issues: Vec<_> = repo
+        .issues()
+        .unwrap_or_abort()
+        .into_iter()
+        .filter(|issue| {
+            let fixes = tracker.fix_commits(issue).unwrap_or_abort();
+            tracker.fixed_in_range(from, to, &fixes).unwrap_or_abort()
+        })
+        .collect();
+    issues.sort_by_key(|issue| issue.initial_message().unwrap_or_abort().time());
+
+    // group the issues by their type
+    let mut groups = BTreeMap::new();
+    let mut untyped = Vec::new();
+    for issue in issues {
+        let head = issue
+            .heads()
+            .abort_on_err()
+            .select_ref(&prios)
+            .map(|head| head.peel(git2::ObjectType::Commit).unwrap_or_abort().id());
+        let acc: HashMap<_, _> = head
+            .into_iter()
+            .flat_map(|head| issue.messages_from(head).abort_on_err())
+            .accumulate_trailers(&[ISSUE_TYPE_SPEC]);
+        let issue_type = acc
+            .get(ISSUE_TYPE_SPEC.key)
+            .cloned()
+            .and_then(|values| values.into_iter().next())
+            .map(|value| value.to_string());
+        match issue_type {
+            Some(t) => groups.entry(t).or_insert_with(Vec::new).push(issue),
+            None    => untyped.push(issue),
+        }
+    }
+
+    let formatter : Vec<FT<_,_>> = tokenvec![" * ", MFT::Subject, " (", MFT::Id(id_len), ")"];
+
+    // present the issues as a Markdown document, with one section per type
+    let mut first = true;
+    let sections = groups
+        .into_iter()
+        .chain(Some((String::from("other"), untyped)))
+        .filter(|section| !section.1.is_empty());
+    for (issue_type, issues) in sections {
+        if !first {
+            println!();
+        }
+        first = false;
+
+        let mut chars = issue_type.chars();
+        let heading: String = chars
+            .next()
+            .into_iter()
+            .flat_map(char::to_uppercase)
+            .chain(chars)
+            .collect();
+        println!("## {}\n", heading);
+
+        issues
+            .into_iter()
+            .map(|issue| issue.initial_message())
+            .abort_on_err()
+            .flat_map(|initial| formatter.iter().formatted_lines(initial))
+            .abort_on_err()
+            .print_lines()
+            .unwrap_or_abort();
+    }
+}
+
+
+/// completions subcommand implementation
+///
+fn completions_impl(matches: &clap::ArgMatches) {
+    let shell = value_t!(matches, "shell", clap::Shell).unwrap_or_else(|err| err.exit());
+    let script = completion::script(App::from_yaml(&cli_yaml()), shell).unwrap_or_abort();
+    print!("{}", script);
+}
+
+
+/// deps subcommand implementation
+///
+fn deps_impl(matches: &clap::ArgMatches) {
+    use display::{TreeGraphElem, TreeGraphElemLine, MarkType};
+    use libgitdit::deps::DependencyGraph;
+    use std::collections::HashSet;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let (graph, closed) = util::dependency_graph(&repo);
+
+    let describe = |id: git2::Oid| {
+        let mut id_str = id.to_string();
+        id_str.truncate(id_len);
+        let subject = repo
+            .find_commit(id)
+            .ok()
+            .and_then(|commit| commit.summary().map(String::from))
+            .unwrap_or_default();
+        let status = if closed.contains(&id) { " (closed)" } else { "" };
+        format!("{} {}{}", id_str, subject, status)
+    };
+
+    if matches.is_present("cycles") {
+        graph.cycles()
+            .into_iter()
+            .map(|cycle| cycle
+                .into_iter()
+                .map(&describe)
+                .collect::<Vec<_>>()
+                .join(" -> ")
+            )
+            .print_lines()
+            .unwrap_or_abort();
+        return;
+    }
+
+    // NOTE: the issue is a required parameter unless cycles are requested
+    let issue = repo.cli_issue(matches).unwrap();
+    let reverse = matches.is_present("reverse");
+
+    // The tree is assembled depth-first. For each level, we track whether
+    // further siblings follow, which determines the glyphs to draw.
+    fn subtree<F>(graph: &DependencyGraph,
+                  id: git2::Oid,
+                  reverse: bool,
+                  describe: &F,
+                  levels: &mut Vec<bool>,
+                  path: &mut HashSet<git2::Oid>,
+                  lines: &mut Vec<String>)
+        where F: Fn(git2::Oid) -> String
+    {
+        let graph_line: TreeGraphElemLine = levels
+            .iter()
+            .enumerate()
+            .flat_map(|(pos, more)| {
+                if pos + 1 == levels.len() {
+                    vec![TreeGraphElem::Mark(MarkType::Mid), TreeGraphElem::Empty, TreeGraphElem::Empty]
+                } else if *more {
+                    vec![TreeGraphElem::Following, TreeGraphElem::Empty, TreeGraphElem::Empty]
+                } else {
+                    vec![TreeGraphElem::Empty; 3]
+                }
+            })
+            .collect();
+        let cycle = path.contains(&id);
+        // The elements following a mark are drawn as dashes, so we strip the
+        // last one in favour of a space.
+        let mut graph_line = graph_line.to_string();
+        if graph_line.pop().is_some() {
+            graph_line.push(' ');
+        }
+        lines.push(format!(
+            "{}{}{}",
+            graph_line,
+            describe(id),
+            if cycle { " [cycle]" } else { "" }
+        ));
+        if cycle {
+            return;
+        }
+
+        let children = if reverse { graph.dependents(id) } else { graph.dependencies(id) };
+        path.insert(id);
+        for (pos, child) in children.iter().enumerate() {
+            levels.push(pos + 1 < children.len());
+            subtree(graph, *child, reverse, describe, levels, path, lines);
+            levels.pop();
+        }
+        path.remove(&id);
+    }
+
+    let mut lines = Vec::new();
+    subtree(&graph, issue.id(), reverse, &describe, &mut vec![], &mut HashSet::new(), &mut lines);
+    lines.print_lines().unwrap_or_abort();
+}
+
+
+/// drafts subcommand implementation
+///
+fn drafts_impl(matches: &clap::ArgMatches) {
+    use libgitdit::draft::{Draft, drafts};
+    use libgitdit::message::LineIteratorExt;
+
+    let repo = util::open_dit_repo();
+
+    match matches.subcommand() {
+        ("edit", Some(sub_matches)) => {
+            // NOTE: the draft is a required parameter
+            let mut draft = Draft::find(&repo, sub_matches.value_of("draft").unwrap())
+                .unwrap_or_abort();
+
+            let path = repo.commitmsg_edit_path(sub_matches);
+            { // write
+                let mut file = File::create(path.as_path()).unwrap_or_abort();
+                file.write_all(draft.text().as_bytes()).unwrap_or_abort();
+                file.flush().unwrap_or_abort();
+            }
+
+            // We keep whatever was written, even if the message is not valid
+            // yet. Problems are reported nevertheless.
+            let result = repo.try_get_commit_msg(&path);
+            let text = std::fs::read_to_string(&path).unwrap_or_abort();
+            draft.update(&text).unwrap_or_abort();
+            result.unwrap_or_abort();
+        },
+        ("publish", Some(sub_matches)) => {
+            let committer = repo.signature().unwrap_or_abort();
+            for name in sub_matches.values_of("draft").into_iter().flatten() {
+                let draft = Draft::find(&repo, name).unwrap_or_abort();
+                let id = util::publish_draft(&repo, draft, &committer).unwrap_or_abort();
+                println!("[dit][publish] {}", id);
+            }
+        },
+        ("drop", Some(sub_matches)) => {
+            for name in sub_matches.values_of("draft").into_iter().flatten() {
+                Draft::find(&repo, name)
+                    .and_then(Draft::discard)
+                    .unwrap_or_abort();
+            }
+        },
+        _ => {
+            drafts(&repo)
+                .unwrap_or_abort()
+                .iter()
+                .map(|draft| {
+                    let subject = draft
+                        .text()
+                        .lines()
+                        .stripped()
+                        .find(|line| !line.is_empty())
+                        .unwrap_or_default();
+                    format!("{} {}", draft.name(), subject)
+                })
+                .print_lines()
+                .unwrap_or_abort();
+        },
+    }
+}
+
+
+/// duplicate subcommand implementation
+///
+fn duplicate_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let author = repo.cli_author(matches);
+    let committer = repo.signature().unwrap_or_abort();
+    let prios = repo.remote_priorization();
+
+    // NOTE: both issues are required parameters
+    let duplicate = util::value_to_issue(&repo, matches.value_of("duplicate").unwrap());
+    let original = util::value_to_issue(&repo, matches.value_of("original").unwrap());
+    if duplicate.id() == original.id() {
+        error!("An issue cannot be a duplicate of itself.");
+        std::process::exit(1);
+    }
+
+    util::mark_duplicate(&duplicate, &original, &author, &committer, &prios)
+        .unwrap_or_abort();
+}
+
+
+/// feed subcommand implementation
+///
+fn feed_impl(matches: &clap::ArgMatches) {
+    use display::{AtomEntry, AtomFeed};
+    use filters::{FilterSpec, MetadataFilter};
+
+    let repo = util::open_dit_repo();
+    let remote_prios = repo.remote_priorization();
+
+    let (issues, id, title) = if let Some(issue) = repo.cli_issue(matches) {
+        let subject = issue
+            .initial_message()
+            .unwrap_or_abort()
+            .summary()
+            .unwrap_or_default()
+            .to_owned();
+        let id = format!("urn:git-dit:issue:{}", issue.id());
+        (vec![issue], id, subject)
+    } else {
+        let mut specs: Vec<FilterSpec> = matches
+            .values_of("filter")
+            .into_iter()
+            .flatten()
+            .map(str::parse)
+            .abort_on_err()
+            .collect();
+        filters::hide_duplicates(&mut specs);
+        let filter = MetadataFilter::new(&remote_prios, specs).unwrap_or_abort();
+
+        let issues = repo
+            .issues()
+            .unwrap_or_abort()
+            .into_iter()
+            .filter(|issue| !issue.is_archived())
+            .filter(|issue| filter.filter(issue))
+            .collect();
+        (issues, "urn:git-dit:issues".to_owned(), "Issues".to_owned())
+    };
+
+    // most recent messages first
+    let mut messages: Vec<_> = issues
+        .iter()
+        .flat_map(|issue| issue.messages().unwrap_or_abort())
+        .abort_on_err()
+        .collect();
+    messages.sort_by_key(|message| std::cmp::Reverse(message.time()));
+    if let Some(number) = matches.value_of("n") {
+        messages.truncate(str::parse(number).unwrap_or_abort());
+    }
+
+    let mut feed = match matches.value_of("link") {
+        Some(link) => {
+            let mut feed = AtomFeed::new(link, matches.value_of("title").unwrap_or(&title));
+            feed.link(link);
+            feed
+        },
+        None => AtomFeed::new(id, matches.value_of("title").unwrap_or(&title)),
+    };
+    for message in messages.iter() {
+        feed.entry(AtomEntry::from_message(message));
+    }
+
+    match matches.value_of("output") {
+        Some(path) => File::create(path)
+            .and_then(|mut file| writeln!(file, "{}", feed))
+            .unwrap_or_abort(),
+        None => println!("{}", feed),
+    }
+}
+
+
 /// fetch subcommand implementation
 ///
 fn fetch_impl(matches: &clap::ArgMatches) {
@@ -206,47 +681,269 @@ fn fetch_impl(matches: &clap::ArgMatches) {
     };
 
     // set the options for the fetch
+    let updates = std::cell::RefCell::new(Vec::new());
+    let quiet = matches.is_present("quiet");
     let mut fetch_options = git2::FetchOptions::new();
     fetch_options.prune(if matches.is_present("prune") {
         git2::FetchPrune::On
     } else {
         git2::FetchPrune::Unspecified
     });
-    fetch_options.remote_callbacks(gitext::callbacks());
+    fetch_options.remote_callbacks(gitext::fetch_callbacks(&updates, !quiet && !matches.is_present("json")));
 
     let refspec_refs : Vec<&str> = refspecs.iter().map(String::as_str).collect();
     remote.fetch(refspec_refs.as_ref(), Some(&mut fetch_options), None)
           .unwrap_or_abort();
+    hooks::post_fetch(&repo, remote.name().unwrap_or_default(), &updates.borrow())
+        .unwrap_or_abort();
+
+    // translate the reference updates into changes of issues
+    let changes = libgitdit::changes::issue_changes(&repo, updates.borrow().iter())
+        .unwrap_or_abort();
+    notify::notify(&repo, &changes).unwrap_or_abort();
+    if quiet {
+        return;
+    }
+    let subject = |id| repo
+        .find_commit(id)
+        .ok()
+        .and_then(|message| message.summary().map(String::from));
+
+    if matches.is_present("json") {
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                let heads: Vec<_> = change
+                    .head_moves
+                    .iter()
+                    .map(|head| json!({
+                        "ref": head.refname,
+                        "old": if head.old.is_zero() { None } else { Some(head.old.to_string()) },
+                        "new": head.new.to_string(),
+                        "status": head.status.as_ref().map(|status| json!({"old": status.0, "new": status.1})),
+                    }))
+                    .collect();
+                json!({
+                    "issue": change.issue.to_string(),
+                    "subject": subject(change.issue),
+                    "new": change.is_new,
+                    "messages": change.new_messages.iter().map(|id| id.to_string()).collect::<Vec<_>>(),
+                    "heads": heads,
+                    "removed": change.is_removed,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(changes));
+        return;
+    }
+
+    for change in changes {
+        let subject = subject(change.issue).unwrap_or_default();
+        if change.is_new {
+            println!("[dit][new] {} {}", change.issue, subject);
+        }
+        if !change.new_messages.is_empty() {
+            println!("[dit][messages] {} {} new message(s) in \"{}\"", change.issue, change.new_messages.len(), subject);
+        }
+        for head in change.head_moves.iter().filter(|head| !head.old.is_zero()) {
+            match head.status {
+                Some((ref old, ref new)) => println!(
+                    "[dit][head] {} moved to {}, status: {} -> {}",
+                    change.issue,
+                    head.new,
+                    old.as_ref().map(String::as_str).unwrap_or("none"),
+                    new.as_ref().map(String::as_str).unwrap_or("none"),
+                ),
+                None => println!("[dit][head] {} moved to {}", change.issue, head.new),
+            }
+        }
+        if change.is_removed {
+            println!("[dit][removed] {}", change.issue);
+        }
+    }
+}
+
+
+/// fixed-in subcommand implementation
+///
+fn fixed_in_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+
+    // NOTE: the issue is a required parameter
+    let issue = repo.cli_issue(matches).unwrap();
+    let tracker = repo.fix_tracker().unwrap_or_abort();
+    let fixes = tracker.fix_commits(&issue).unwrap_or_abort();
+    if fixes.is_empty() {
+        warn!("The issue does not reference any fix commits.");
+        std::process::exit(1);
+    }
+
+    if matches.is_present("matrix") {
+        // present the fixes in chronological order
+        let mut fixes: Vec<Commit> = fixes
+            .iter()
+            .map(|id| repo.find_commit(*id))
+            .abort_on_err()
+            .collect();
+        fixes.sort_by_key(Commit::time);
+        let columns = fixes.len();
+
+        // The matrix is displayed similar to the output of `git show-branch`,
+        // but with one column per fix and one row per branch.
+        let header = fixes
+            .iter()
+            .enumerate()
+            .map(|(pos, fix)| format!(
+                "{}!{} {:.*} {}",
+                " ".repeat(pos),
+                " ".repeat(columns - pos - 1),
+                id_len,
+                fix.id(),
+                fix.summary().unwrap_or_default()
+            ))
+            .chain(Some("-".repeat(columns)));
+
+        let rows = repo
+            .branches(None)
+            .abort_on_err()
+            .map(|(branch, _)| branch.into_reference())
+            .filter(|r| IssueRefType::of_ref(r.name().unwrap_or_default()).is_none())
+            .filter_map(|r| r
+                .peel(git2::ObjectType::Commit)
+                .ok()
+                .map(|target| (r.shorthand().unwrap_or_default().to_owned(), target.id()))
+            )
+            .map(|(name, target)| {
+                let marks: String = fixes
+                    .iter()
+                    .map(|fix| if tracker.contains_fix(target, fix.id()).unwrap_or_abort() {
+                        '+'
+                    } else {
+                        ' '
+                    })
+                    .collect();
+                format!("{} {}", marks, name)
+            });
+
+        let result = header
+            .chain(rows)
+            .pipe_lines(repo.pager())
+            .unwrap_or_abort();
+        std::process::exit(result);
+    }
+
+    // assemble the globs for the references to consider
+    let mut globs = Vec::new();
+    if !matches.is_present("branches") {
+        globs.push("refs/tags/*");
+    }
+    if !matches.is_present("tags") {
+        globs.push("refs/heads/*");
+        globs.push("refs/remotes/*");
+    }
+
+    globs.into_iter()
+        .flat_map(|glob| tracker.refs_containing(glob, &fixes).unwrap_or_abort())
+        .map(|r| r.shorthand().unwrap_or_default().to_owned())
+        .print_lines()
+        .unwrap_or_abort();
+}
+
+
+/// fsck subcommand implementation
+///
+fn fsck_impl(matches: &clap::ArgMatches) {
+    use libgitdit::fsck::IntegrityCheck;
+
+    let repo = util::open_dit_repo();
+    let check = IntegrityCheck::new(&repo).resolver(util::issue_resolver(&repo));
+
+    let problems = match repo.cli_issues(matches) {
+        Some(issues) => issues
+            .into_iter()
+            .flat_map(|issue| {
+                let globs = [
+                    format!("refs/dit/{}/**", issue.id()),
+                    format!("refs/remotes/*/dit/{}/**", issue.id()),
+                ];
+                let mut problems: Vec<_> = globs
+                    .iter()
+                    .flat_map(|glob| check.check_refs(glob).unwrap_or_abort())
+                    .collect();
+                problems.append(&mut check.check_issue(&issue).unwrap_or_abort());
+                problems
+            })
+            .collect(),
+        None => check.check_all().unwrap_or_abort(),
+    };
+
+    let fix = matches.is_present("fix");
+    let mut result = 0;
+    let lines: Vec<String> = problems
+        .into_iter()
+        .map(|problem| if fix && problem.is_fixable() {
+            problem.fix(&repo).unwrap_or_abort();
+            format!("{} (fixed)", problem)
+        } else {
+            result = 1;
+            problem.to_string()
+        })
+        .collect();
+    lines.print_lines().unwrap_or_abort();
+
+    std::process::exit(result);
 }
 
 
 /// gc subcommand implementation
 ///
+/// Defaults for the options may be configured via the `gc.dit.*` section of the
+/// git configuration.
+///
 fn gc_impl(matches: &clap::ArgMatches) {
-    use libgitdit::gc::ReferenceCollectionSpec;
+    use libgitdit::gc;
     use libgitdit::iter::ReferenceDeletingIter;
 
     let repo = util::open_dit_repo();
+    let config = repo.config().unwrap_or_abort();
 
     let collect = {
-        let collect_heads = if matches.is_present("collect-heads") {
-            ReferenceCollectionSpec::BackedByRemoteHead
+        let spec = if matches.is_present("collect-heads") {
+            Some("backed".to_owned())
         } else {
-            ReferenceCollectionSpec::Never
+            matches
+                .value_of("head-policy")
+                .map(String::from)
+                .or_else(|| config.get_string("gc.dit.collectHeads").ok())
         };
+        let collect_heads = util::parse_head_policy(&repo, spec.as_deref()).unwrap_or_abort();
+        let consider_remote = matches.is_present("consider-remote") ||
+            config.get_bool("gc.dit.considerRemote").unwrap_or(false);
         repo.collectable_refs()
-            .consider_remote_refs(matches.is_present("consider-remote"))
+            .consider_remote_refs(consider_remote)
             .collect_heads(collect_heads)
+            .remote_priorities(repo.remote_priorization().remotes().to_vec())
     };
 
-    let refs = repo
+    let mut refs: Vec<_> = repo
         .cli_issues(matches)
         .unwrap_or_else(|| repo.issues().unwrap_or_abort())
         .into_iter()
         .map(|issue| collect.for_issue(&issue))
         .abort_on_err()
         .flat_map(|collector| collector)
-        .abort_on_err();
+        .abort_on_err()
+        .collect();
+
+    if matches.is_present("stale-remotes") || config.get_bool("gc.dit.staleRemotes").unwrap_or(false) {
+        refs.append(&mut collect.stale_remote_refs().unwrap_or_abort());
+    }
+
+    let expiry = matches
+        .value_of("expire-reflogs")
+        .map(|days| str::parse(days).unwrap_or_abort())
+        .or_else(|| config.get_i64("gc.dit.reflogExpire").ok());
 
     if matches.is_present("dry-run") {
         refs.into_iter()
@@ -255,7 +952,113 @@ fn gc_impl(matches: &clap::ArgMatches) {
             .unwrap_or_abort();
     } else {
         ReferenceDeletingIter::from(refs).print_lines().unwrap_or_abort();
+        if let Some(days) = expiry {
+            let before = chrono::Utc::now().timestamp() - days * 24 * 60 * 60;
+            gc::expire_reflogs(&repo, before).unwrap_or_abort();
+        }
+    }
+}
+
+
+/// graph subcommand implementation
+///
+fn graph_impl(matches: &clap::ArgMatches) {
+    use display::DotGraph;
+    use std::collections::HashSet;
+
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+
+    let label = |id: git2::Oid| {
+        let mut label = id.to_string();
+        label.truncate(id_len);
+        if let Some(summary) = repo.find_commit(id).ok().as_ref().and_then(git2::Commit::summary) {
+            label.push('\n');
+            label.push_str(summary);
+        }
+        label
+    };
+
+    if matches.is_present("deps") {
+        let (graph, closed) = util::dependency_graph(&repo);
+
+        // Edges which are part of a cycle are highlighted.
+        let mut cycle_edges = HashSet::new();
+        for cycle in graph.cycles() {
+            let successors = cycle.iter().skip(1).chain(cycle.first());
+            cycle_edges.extend(cycle.iter().cloned().zip(successors.cloned()));
+        }
+
+        let mut dot = DotGraph::new("dependencies");
+        for (issue, dependency) in graph.edges() {
+            for id in &[issue, dependency] {
+                let mut attrs = vec![("label", label(*id))];
+                if closed.contains(id) {
+                    attrs.push(("style", "dashed".to_owned()));
+                }
+                dot.node(id.to_string(), attrs);
+            }
+            let attrs = if cycle_edges.contains(&(issue, dependency)) {
+                vec![("color", "red".to_owned())]
+            } else {
+                vec![]
+            };
+            dot.edge(issue.to_string(), dependency.to_string(), attrs);
+        }
+        println!("{}", dot);
+        return;
+    }
+
+    // NOTE: the issue is a required parameter unless the dependency graph is
+    //       requested
+    let issue = repo.cli_issue(matches).unwrap();
+    let messages: Vec<_> = issue.messages().unwrap_or_abort().abort_on_err().collect();
+    let message_ids: HashSet<_> = messages.iter().map(git2::Commit::id).collect();
+    let heads: HashSet<_> = issue
+        .heads()
+        .abort_on_err()
+        .filter_map(|head| head.peel(git2::ObjectType::Commit).ok())
+        .map(|head| head.id())
+        .collect();
+
+    let mut dot = DotGraph::new(issue.id().to_string());
+    for message in messages.iter() {
+        let mut attrs = vec![("label", label(message.id()))];
+        if heads.contains(&message.id()) {
+            attrs.push(("peripheries", "2".to_owned()));
+        }
+        dot.node(message.id().to_string(), attrs);
     }
+    for message in messages.iter() {
+        for (pos, parent) in message.parent_ids().enumerate() {
+            // Only the first parent of a reply denotes the replied message.
+            // Other parents are mere references.
+            if pos == 0 && message.id() != issue.id() {
+                dot.edge(message.id().to_string(), parent.to_string(), vec![]);
+                continue;
+            }
+
+            if !message_ids.contains(&parent) {
+                let other_issue = repo
+                    .find_commit(parent)
+                    .ok()
+                    .and_then(|commit| repo.issue_with_message(&commit).ok())
+                    .map(|other| other.id());
+                let attrs = match other_issue {
+                    Some(other) => {
+                        let mut other_id = other.to_string();
+                        other_id.truncate(id_len);
+                        let label = format!("{}\n(issue {})", label(parent), other_id);
+                        vec![("label", label), ("style", "dashed".to_owned())]
+                    },
+                    None => vec![("label", label(parent)), ("shape", "box".to_owned())],
+                };
+                dot.node(parent.to_string(), attrs);
+            }
+            dot.edge(message.id().to_string(), parent.to_string(), vec![("style", "dashed".to_owned())]);
+        }
+    }
+    println!("{}", dot);
 }
 
 
@@ -266,18 +1069,27 @@ fn list_impl(matches: &clap::ArgMatches) {
     use libgitdit::Issue;
 
     use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
-    use filters::MetadataFilter;
+    use filters::{FilterSpec, MetadataFilter};
 
     let repo = util::open_dit_repo();
     let remote_prios = repo.remote_priorization();
 
     // construct filter
-    let filter = match matches.values_of("filter") {
-        Some(values) => {
-            let specs = values.map(str::parse).abort_on_err();
-            MetadataFilter::new(&remote_prios, specs).unwrap_or_abort()
-        },
-        None         => MetadataFilter::empty(&remote_prios),
+    let filter = {
+        let mut specs: Vec<FilterSpec> = matches
+            .values_of("filter")
+            .into_iter()
+            .flatten()
+            .map(str::parse)
+            .abort_on_err()
+            .collect();
+
+        // Issues marked as duplicates are hidden unless requested explicitly,
+        // either via the option or by filtering for a status.
+        if !matches.is_present("duplicates") {
+            filters::hide_duplicates(&mut specs);
+        }
+        MetadataFilter::new(&remote_prios, specs).unwrap_or_abort()
     };
 
     let id_len = repo.abbreviation_length(matches);
@@ -297,13 +1109,52 @@ fn list_impl(matches: &clap::ArgMatches) {
     };
 
     // get initial commits
+    let archived = matches.is_present("archived");
     let mut issues : Vec<Issue> = repo
         .issues()
         .unwrap_or_abort()
         .into_iter()
+        .filter(|issue| issue.is_archived() == archived)
         .filter(|issue| filter.filter(issue))
         .collect();
 
+    // optionally limit to issues which are ready or blocked
+    if matches.is_present("ready") || matches.is_present("blocked") {
+        let (graph, closed) = util::dependency_graph(&repo);
+        let ready = matches.is_present("ready");
+        issues.retain(|issue| {
+            let is_ready = graph.is_ready(issue.id(), |id| closed.contains(&id));
+            if ready {
+                is_ready && !closed.contains(&issue.id())
+            } else {
+                !is_ready
+            }
+        });
+    }
+
+    // optionally limit to issues fixed in a specific revision
+    if let Some(rev) = matches.value_of("fixed-in") {
+        let target = repo.value_to_commit(rev).id();
+        let tracker = repo.fix_tracker().unwrap_or_abort();
+        issues.retain(|issue| {
+            let fixes = tracker.fix_commits(issue).unwrap_or_abort();
+            tracker.contains_fixes(target, &fixes).unwrap_or_abort()
+        });
+    }
+
+    // optionally limit to issues with messages not marked as read, counting
+    // them for display
+    let mut unread_counts = std::collections::HashMap::new();
+    if matches.is_present("unread") {
+        for issue in &issues {
+            let count = issue.unread_messages().unwrap_or_abort().abort_on_err().count();
+            if count > 0 {
+                unread_counts.insert(issue.id(), count);
+            }
+        }
+        issues.retain(|issue| unread_counts.contains_key(&issue.id()));
+    }
+
     // descending order
     let mut sort_key : Box<FnMut(&Issue) -> git2::Time> = Box::new(|ref issue| issue
         .initial_message()
@@ -317,22 +1168,43 @@ fn list_impl(matches: &clap::ArgMatches) {
         issues.truncate(str::parse(number).unwrap_or_abort());
     }
 
-    // present the list to the user
+    // present the list to the user, along with the number of unread messages
+    // if requested
     let result = issues
         .into_iter()
-        .map(|issue| issue.initial_message())
-        .abort_on_err()
-        .flat_map(|initial| formatter.iter().formatted_lines(initial))
-        .abort_on_err()
+        .map(|issue| (issue.initial_message().unwrap_or_abort(), unread_counts.get(&issue.id()).cloned()))
+        .flat_map(|(initial, unread)| formatter
+            .iter()
+            .formatted_lines(initial)
+            .abort_on_err()
+            .enumerate()
+            .map(move |(number, line)| match unread {
+                Some(unread) if number == 0 => format!("{} [{} unread]", line, unread),
+                _ => line,
+            })
+        )
         .pipe_lines(repo.pager())
         .unwrap_or_abort();
     std::process::exit(result);
 }
 
 
+/// mark-read subcommand implementation
+///
+fn mark_read_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let sig = repo.signature().unwrap_or_abort();
+
+    for issue in repo.cli_issues(matches).unwrap_or_else(|| repo.issues().unwrap_or_abort()) {
+        issue.mark_read(&sig).unwrap_or_abort();
+    }
+}
+
+
 /// new subcommand implementation
 ///
 fn mirror_impl(matches: &clap::ArgMatches) {
+    use libgitdit::changes::RefUpdate;
     use std::collections::HashSet;
     use gitext::{RemotePriorization, ReferrenceExt, ReferrencesExt};
 
@@ -351,6 +1223,9 @@ fn mirror_impl(matches: &clap::ArgMatches) {
         .cli_issues(matches)
         .unwrap_or_else(|| repo.issues().unwrap_or_abort());
 
+    // references updated, for notifications
+    let mut updates = Vec::new();
+
     for issue in issues {
         if clone_head || update_head {
             // take care about the head reference
@@ -359,9 +1234,21 @@ fn mirror_impl(matches: &clap::ArgMatches) {
                     .peel(git2::ObjectType::Commit)
                     .unwrap_or_abort()
                     .id();
+                let old = issue
+                    .local_head()
+                    .ok()
+                    .and_then(|head| head.target())
+                    .unwrap_or_else(git2::Oid::zero);
+                if old != id {
+                    let refname = format!("refs/dit/{}/head", issue.id());
+                    hooks::pre_head_update(&repo, issue.id(), &refname, old, id).unwrap_or_abort();
+                }
                 // TODO: Failure to update a head ref should probably result in
                 //       a warning instead of a hard error.
-                issue.update_head(id, update_head).unwrap_or_abort();
+                let head = issue.update_head(id, update_head).unwrap_or_abort();
+                if old != id {
+                    updates.push(RefUpdate::new(head.name().unwrap_or_default(), old, id));
+                }
             }
         }
 
@@ -423,25 +1310,43 @@ fn mirror_impl(matches: &clap::ArgMatches) {
 
             // create refs for remaining leaves
             for leaf in leaves {
-                issue.add_leaf(leaf).unwrap_or_abort();
+                let reference = issue.add_leaf(leaf).unwrap_or_abort();
+                updates.push(RefUpdate::new(reference.name().unwrap_or_default(), git2::Oid::zero(), leaf));
             }
         }
     }
+
+    notify::notify_updates(&repo, &updates).unwrap_or_abort();
 }
 
 
 /// new subcommand implementation
 ///
 fn new_impl(matches: &clap::ArgMatches) {
-    use util::message_from_args;
+    use util::{MessageTarget, message_from_args};
 
     let repo = util::open_dit_repo();
     let author = repo.cli_author(matches);
     let committer = repo.signature().unwrap_or_abort();
 
+    // store attachments and load the template before the user spends time
+    // writing the message
+    let attachments = repo.cli_attachments(matches);
+    let tree = libgitdit::attachment::message_tree(&repo, None, attachments).unwrap_or_abort();
+    let template = matches
+        .value_of("template")
+        .map(|name| util::load_template(&repo, name));
+
+    // text which can't be used right away is saved as a draft
+    let save_draft = |text: &str| util::save_draft(&repo, None, &author, &committer, text, &tree, &[]);
+
     // get the message, either from the command line argument or an editor
     let message = if let Some(m) = message_from_args(matches) {
         // the message was supplied via the command line
+        if template.is_some() {
+            warn!("Template will only be used if an editor is used.");
+        }
+
         m.into_iter()
          .chain(repo.prepare_trailers(matches)
                     .into_iter()
@@ -455,19 +1360,38 @@ fn new_impl(matches: &clap::ArgMatches) {
 
         { // write
             let mut file = File::create(path.as_path()).unwrap_or_abort();
+            if let Some(ref template) = template {
+                template
+                    .lines()
+                    .iter()
+                    .write_lines(&mut file)
+                    .unwrap_or_abort();
+            }
             repo.prepare_trailers(matches)
                 .write_lines(&mut file)
                 .unwrap_or_abort();
             file.flush().unwrap_or_abort();
         }
 
-        repo.get_commit_msg(path)
+        util::get_commit_msg_or_draft(&repo, &path, save_draft)
     }.into_iter().collect_string();
 
-    // commit the message
-    let tree = repo.empty_tree().unwrap_or_abort();
-    let id = repo
-        .create_issue(&author, &committer, message.trim(), &tree, Vec::new())
+    if matches.is_present("draft") {
+        save_draft(&message);
+        return;
+    }
+
+    // make sure the trailers required by the template are present
+    if let Some(template) = template {
+        template
+            .check(message.lines())
+            .inspect_err(|_| save_draft(&message))
+            .unwrap_or_abort();
+    }
+
+    // commit the message, which the dit hooks may reject
+    let id = util::create_message(&repo, MessageTarget::NewIssue, &author, &committer, &message, &tree, &[])
+        .inspect_err(|_| save_draft(&message))
         .unwrap_or_abort();
     println!("[dit][new] {}", id);
 }
@@ -508,10 +1432,41 @@ fn push_impl(matches: &clap::ArgMatches) {
 }
 
 
+/// refs-to subcommand implementation
+///
+fn refs_to_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+
+    // NOTE: the target is a required parameter
+    let target = repo.value_to_commit(matches.value_of("target").unwrap());
+
+    // An issue is referred to by references to any of its messages. Like in
+    // the "show" subcommand, we don't list the issue's own messages.
+    let targets: std::collections::HashSet<_> = match repo.find_issue(target.id()) {
+        Ok(issue) => issue
+            .messages()
+            .unwrap_or_abort()
+            .abort_on_err()
+            .map(|message| message.id())
+            .collect(),
+        Err(_) => Some(target.id()).into_iter().collect(),
+    };
+
+    libgitdit::backlinks::backlinks(&repo, targets.iter().cloned())
+        .unwrap_or_abort()
+        .iter()
+        .filter(|link| !targets.contains(&link.message))
+        .map(|link| util::describe_backlink(&repo, link, id_len))
+        .print_lines()
+        .unwrap_or_abort();
+}
+
+
 /// reply subcommand implementation
 ///
 fn reply_impl(matches: &clap::ArgMatches) {
-    use util::message_from_args;
+    use util::{MessageTarget, message_from_args};
 
     let repo = util::open_dit_repo();
     let author = repo.cli_author(matches);
@@ -526,9 +1481,14 @@ fn reply_impl(matches: &clap::ArgMatches) {
     // and get all the info from it that we might need
     let mut parent = repo.value_to_commit(matches.value_of("parent").unwrap());
 
-    // extract the subject and tree from the parent
+    // extract the subject and tree from the parent, replacing the parent's
+    // attachments with our own
     let subject = parent.reply_subject();
-    let tree = parent.tree().unwrap_or_abort();
+    let tree = libgitdit::attachment::message_tree(
+        &repo,
+        Some(&parent.tree().unwrap_or_abort()),
+        repo.cli_attachments(matches)
+    ).unwrap_or_abort();
 
     // figure out to what issue we reply
     let issue = repo.issue_with_message(&parent).unwrap_or_abort();
@@ -536,6 +1496,12 @@ fn reply_impl(matches: &clap::ArgMatches) {
     // get the references specified on the command line
     let references = repo.cli_references(matches);
 
+    // text which can't be used right away is saved as a draft
+    let save_draft = |text: &str| {
+        let parent_refs: Vec<&Commit> = Some(&parent).into_iter().chain(references.iter()).collect();
+        util::save_draft(&repo, Some(issue.id()), &author, &committer, text, &tree, &parent_refs)
+    };
+
     // get the message, either from the command line argument or an editor
     let message = if let Some(m) = message_from_args(matches) {
         // the message was supplied via the command line
@@ -572,20 +1538,49 @@ fn reply_impl(matches: &clap::ArgMatches) {
             repo.prepare_trailers(matches)
                 .write_lines(&mut file)
                 .unwrap_or_abort();
+
+            // Provide the thread replied to and the issue's current metadata
+            // as comments, which will be stripped after editing.
+            writeln!(&mut file).unwrap_or_abort();
+            util::reply_context(&issue, &parent, &repo.remote_priorization())
+                .into_iter()
+                .commented()
+                .write_lines(&mut file)
+                .unwrap_or_abort();
             file.flush().unwrap_or_abort();
         }
 
-        repo.get_commit_msg(path)
+        util::get_commit_msg_or_draft(&repo, &path, save_draft)
     }.into_iter().collect_string();
 
+    if matches.is_present("draft") {
+        save_draft(&message);
+        return;
+    }
+
     // construct a vector holding all parents
-    let parent_refs = Some(&parent).into_iter().chain(references.iter());
+    let parent_refs: Vec<&Commit> = Some(&parent).into_iter().chain(references.iter()).collect();
+
+    // finally, create the message, which the dit hooks may reject
+    util::create_message(&repo, MessageTarget::Leaf(&issue), &author, &committer, &message, &tree, &parent_refs)
+        .inspect_err(|_| save_draft(&message))
+        .unwrap_or_abort();
+}
 
-    // finally, create the message
-    issue.add_message(&author, &committer, message.trim(), &tree, parent_refs)
-         .unwrap_or_abort();
+/// serve subcommand implementation
+///
+#[cfg(feature = "serve")]
+fn serve_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+
+    // NOTE: the listen address has a default value
+    let addr = matches.value_of("listen").unwrap();
+    serve::Server::new(&repo, matches.is_present("write"))
+        .run(addr)
+        .unwrap_or_abort();
 }
 
+
 /// show subcommand implementation
 ///
 fn show_impl(matches: &clap::ArgMatches) {
@@ -594,6 +1589,8 @@ fn show_impl(matches: &clap::ArgMatches) {
     use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
     use display::{IntoTreeGraph, TreeGraphElem, TreeGraphElemLine};
     use gitext::ReferrencesExt;
+    use libgitdit::trailer::spec::{ISSUE_REF_SPEC, SPLIT_TO_SPEC};
+    use libgitdit::xref::IssueRef;
 
     let repo = util::open_dit_repo();
     let id_len = repo.abbreviation_length(matches);
@@ -602,35 +1599,26 @@ fn show_impl(matches: &clap::ArgMatches) {
     // NOTE: the issue is a required parameter
     let issue = repo.cli_issue(matches).unwrap();
 
-    // translate commit to lines representing the commit
-    let formatter : Vec<FT<_,_>> = if matches.is_present("msgtree") {
-        // With the "tree" option, we only display subjects in a short
-        // format
-        tokenvec![MFT::Id(id_len), " ", MFT::Author, " ", MFT::Subject]
-    } else {
-        let head = issue
-            .heads()
-            .abort_on_err()
-            .select_ref(&prios)
-            .unwrap() // TODO: abort gracefully
-            .target()
-            .unwrap(); // TODO: abort gracefully
-
-        tokenvec![
-            MFT::Id(id_len), MFT::IfId(head, tokenvec![" (head)"]), FT::LineEnd,
-            "Author: ", MFT::Author, FT::LineEnd,
-            "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
-            FT::LineEnd,
-            MFT::Subject, FT::LineEnd,
-            FT::LineEnd,
-            MFT::Body,
-            FT::LineEnd,
-            FT::LineEnd]
-    };
-
     // first, get us an iterator over all the commits
     let mut commits : Vec<(TreeGraphElemLine, Commit)> =
-        if matches.is_present("initial") {
+        if matches.is_present("with-duplicates") {
+            // The messages of all the threads are interleaved in chronological
+            // order, so we can't draw a tree. Messages may be reachable from
+            // multiple threads via references, which `chronological()` takes
+            // care of.
+            let messages = Some(issue.messages().unwrap_or_abort())
+                .into_iter()
+                .chain(util::duplicates_of(&repo, &issue)
+                    .into_iter()
+                    .map(|duplicate| duplicate.messages().unwrap_or_abort())
+                )
+                .flat_map(|messages| messages.abort_on_err());
+            libgitdit::iter::chronological(&repo, messages)
+                .unwrap_or_abort()
+                .into_iter()
+                .map(|message| (TreeGraphElemLine::empty(), message))
+                .collect()
+        } else if matches.is_present("initial") {
             vec![(
                 TreeGraphElemLine::empty(),
                 issue.initial_message().unwrap_or_abort()
@@ -652,6 +1640,119 @@ fn show_impl(matches: &clap::ArgMatches) {
         }
     };
 
+    // Messages not marked as read are highlighted if requested.
+    let unread_marks: Vec<FT<_,_>> = if matches.is_present("unread") {
+        let unread = issue
+            .unread_messages()
+            .unwrap_or_abort()
+            .abort_on_err()
+            .map(|message| message.id())
+            .collect();
+        tokenvec![MFT::IfIdIn(std::rc::Rc::new(unread), tokenvec![" (unread)"])]
+    } else {
+        Vec::new()
+    };
+
+    // translate commit to lines representing the commit
+    let formatter : Vec<FT<_,_>> = if matches.is_present("msgtree") {
+        // With the "tree" option, we only display subjects in a short
+        // format
+        let mut tokens = tokenvec![MFT::Id(id_len), " ", MFT::Author, " ", MFT::Subject];
+        tokens.extend(unread_marks);
+        tokens
+    } else {
+        let head = issue
+            .heads()
+            .abort_on_err()
+            .select_ref(&prios)
+            .unwrap() // TODO: abort gracefully
+            .target()
+            .unwrap(); // TODO: abort gracefully
+
+        let mut tokens = tokenvec![MFT::Id(id_len), MFT::IfId(head, tokenvec![" (head)"])];
+        tokens.extend(unread_marks);
+        tokens.append(&mut tokenvec![
+            FT::LineEnd,
+            "Author: ", MFT::Author, FT::LineEnd,
+            "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd]);
+
+        // Render split points as links to the issue split off or the message
+        // an issue was split off from. References to issues in other
+        // repositories are rendered along with the issue's subject and status.
+        let resolver = util::issue_resolver(&repo);
+        let mut attachment_tokens = Vec::new();
+        for message in commits.iter().map(|commit| &commit.1) {
+            // Attachments are listed below the message's body.
+            for attachment in libgitdit::attachment::attachments(&repo, message).unwrap_or_abort() {
+                let text = format!("Attachment: {} ({} bytes)", attachment.name(), attachment.size());
+                attachment_tokens.push(MFT::IfId(message.id(), tokenvec![text, FT::LineEnd]).into());
+            }
+
+
+            for trailer in message.trailers() {
+                let text = if trailer.key.as_ref() == SPLIT_TO_SPEC.key {
+                    format!("Split to: issue {}", trailer.value)
+                } else if trailer.key.as_ref() == ISSUE_REF_SPEC.key {
+                    let value = trailer.value.to_string();
+                    let summary = value
+                        .parse::<IssueRef>()
+                        .ok()
+                        .and_then(|reference| resolver.resolve(&reference).unwrap_or_abort())
+                        .map(|other| util::issue_summary(&other, &prios))
+                        .unwrap_or_else(|| "(not found)".to_owned());
+                    format!("Refers to: {} {}", value, summary)
+                } else {
+                    continue
+                };
+                tokens.push(MFT::IfId(message.id(), tokenvec![text, FT::LineEnd]).into());
+            }
+
+            // Only initial messages may have been split off.
+            if repo.find_issue(message.id()).is_err() {
+                continue;
+            }
+            for parent in message.parents() {
+                if let Ok(other) = repo.issue_with_message(&parent) {
+                    let text = format!("Split from: message {} of issue {}", parent.id(), other.id());
+                    tokens.push(MFT::IfId(message.id(), tokenvec![text, FT::LineEnd]).into());
+                }
+            }
+        }
+
+        tokens.append(&mut tokenvec![
+            FT::LineEnd,
+            MFT::Subject, FT::LineEnd,
+            FT::LineEnd,
+            MFT::Body,
+            FT::LineEnd]);
+        tokens.append(&mut attachment_tokens);
+        tokens.push(FT::LineEnd);
+        tokens
+    };
+
+    // Messages of other issues referring to any message of this issue are
+    // listed in a separate section. Finding them requires walking all the
+    // messages in the repository, so we only do so on request.
+    let referenced_by: Vec<String> = if matches.is_present("backlinks") {
+        let own_messages: std::collections::HashSet<_> = commits
+            .iter()
+            .map(|commit| commit.1.id())
+            .collect();
+        libgitdit::backlinks::backlinks(&repo, own_messages.iter().cloned())
+            .unwrap_or_abort()
+            .iter()
+            .filter(|link| !own_messages.contains(&link.message))
+            .map(|link| format!("  {}", util::describe_backlink(&repo, link, id_len)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let referenced_by = if referenced_by.is_empty() {
+        referenced_by
+    } else {
+        Some("Referenced by:".to_owned()).into_iter().chain(referenced_by).collect()
+    };
+
     // Transform the simple graph element line into an iterator over lines to
     // print via multiple steps.
     let result = commits
@@ -671,12 +1772,80 @@ fn show_impl(matches: &clap::ArgMatches) {
         )
         // combine each line of graph elements and message
         .map(|line| format!("{} {}", line.0, line.1))
+        .chain(referenced_by)
         .pipe_lines(repo.pager())
         .unwrap_or_abort();
 
     std::process::exit(result);
 }
 
+/// split subcommand implementation
+///
+fn split_impl(matches: &clap::ArgMatches) {
+    use util::message_from_args;
+
+    let repo = util::open_dit_repo();
+    let author = repo.cli_author(matches);
+    let committer = repo.signature().unwrap_or_abort();
+
+    // NOTE: like in the "reply" subcommand, we want to report errors before a
+    //       user spent time writing a message in her editor.
+
+    // the unwrap is safe since `split-message` is a required value
+    let split = repo.value_to_commit(matches.value_of("split-message").unwrap());
+    let issue = repo.issue_with_message(&split).unwrap_or_abort();
+
+    let quote: Vec<String> = Some(format!("Split off from issue {}:", issue.id()))
+        .into_iter()
+        .chain(Some(String::new()))
+        .chain(split.message_lines().quoted())
+        .chain(Some(String::new()))
+        .collect();
+
+    // text which can't be used right away is saved as a draft
+    let tree = repo.empty_tree().unwrap_or_abort();
+    let save_draft = |text: &str| util::save_draft(&repo, None, &author, &committer, text, &tree, &[&split]);
+
+    // get the message, either from the command line argument or an editor
+    let message = if let Some(m) = message_from_args(matches) {
+        // the message was supplied via the command line
+        m.into_iter()
+         .chain(quote)
+         .chain(repo.prepare_trailers(matches)
+                    .into_iter()
+                    .map(|t| t.to_string()))
+         .collect()
+    } else {
+        // we need an editor
+
+        // get the path where we want to edit the message
+        let path = repo.commitmsg_edit_path(matches);
+
+        { // write
+            let mut file = File::create(path.as_path()).unwrap_or_abort();
+            if let Some(s) = split.summary() {
+                write!(&mut file, "{}\n\n", s).unwrap_or_abort();
+            }
+            quote.write_lines(&mut file).unwrap_or_abort();
+            repo.prepare_trailers(matches)
+                .write_lines(&mut file)
+                .unwrap_or_abort();
+            file.flush().unwrap_or_abort();
+        }
+
+        util::get_commit_msg_or_draft(&repo, &path, save_draft)
+    }.into_iter().collect_string();
+
+    // The new issue and the notice left in the original thread may be rejected
+    // by the dit hooks.
+    let new_issue = util::split_issue(&repo, &split, &author, &committer, &message)
+        .inspect_err(|_| save_draft(&message))
+        .unwrap_or_abort();
+
+    println!("[dit][split] {}", new_issue);
+}
+
+
 /// tag subcommand implementation
 ///
 fn tag_impl(matches: &clap::ArgMatches) {
@@ -730,20 +1899,187 @@ fn tag_impl(matches: &clap::ArgMatches) {
         return;
     }
 
-    // construct the message
-    let message = [head_commit.reply_subject().unwrap_or_default(), String::new()]
-        .to_vec()
+    util::tag_issue(&issue, &mut head_commit, &author, &committer, trailers, &references)
+        .unwrap_or_abort();
+}
+
+
+/// transfer subcommand implementation
+///
+fn transfer_impl(matches: &clap::ArgMatches) {
+    use libgitdit::trailer::Trailer;
+    use libgitdit::trailer::spec::{MOVED_FROM_SPEC, MOVED_TO_SPEC};
+    use libgitdit::transfer::Transfer;
+    use util::MessageTarget;
+
+    let repo = util::open_dit_repo();
+    let author = repo.cli_author(matches);
+    let committer = repo.signature().unwrap_or_abort();
+    let prios = repo.remote_priorization();
+
+    // NOTE: the issue and the target are required parameters
+    let issue = repo.cli_issue(matches).unwrap();
+    let target = matches.value_of("target").unwrap();
+    let source = matches
+        .value_of("source-name")
+        .map(String::from)
+        .unwrap_or_else(|| repo
+            .workdir()
+            .unwrap_or_else(|| repo.path())
+            .to_string_lossy()
+            .trim_end_matches('/')
+            .to_owned()
+        );
+
+    let head = util::selected_head(&issue, &prios).unwrap_or_abort().id();
+
+    let transfer = if matches.is_present("reparent") {
+        Transfer::reparented(issue).unwrap_or_abort()
+    } else {
+        Transfer::new(issue)
+    };
+
+    // The head of the issue in the target refers back to the source.
+    let mut target_head = repo.find_commit(transfer.map(head)).unwrap_or_abort();
+    let message = [
+        target_head.reply_subject().unwrap_or_default(),
+        String::new(),
+        format!("This issue was moved from {}.", source),
+        String::new(),
+        Trailer::new(MOVED_FROM_SPEC.key, &format!("{} {}", source, transfer.issue().id())).to_string(),
+    ].to_vec().into_iter().collect_string();
+    let tree = libgitdit::attachment::message_tree(
+        &repo,
+        Some(&target_head.tree().unwrap_or_abort()),
+        Vec::new()
+    ).unwrap_or_abort();
+    // The message is not part of an issue in this repository. Hence, only the
+    // dit-pre-message hook is run.
+    let message = hooks::pre_message(&repo, Some(transfer.issue_id()), Some(target_head.id()), &message)
+        .unwrap_or_abort();
+    let moved_from = repo
+        .commit(None, &author, &committer, message.trim(), &tree, &[&target_head])
+        .unwrap_or_abort();
+
+    // We push the references via temporary references in a separate namespace
+    // since they don't belong to the issue in this repository.
+    let refspecs: Vec<String> = transfer
+        .refs(moved_from)
+        .unwrap_or_abort()
         .into_iter()
-        .chain(trailers.into_iter().map(|t| t.to_string()))
-        .collect_string();
-    let tree = repo.empty_tree().unwrap_or_abort();
-    let parent_refs : Vec<&Commit> = Some(&head_commit).into_iter().chain(references.iter()).collect();
-    let new = repo
-        .commit(None, &author, &committer, message.trim(), &tree, &parent_refs)
+        .map(|(name, id)| {
+            let temporary = format!("refs/dit-transfer/{}", name.trim_start_matches("refs/"));
+            repo.reference(&temporary, id, true, "git-dit: prepare transfer").unwrap_or_abort();
+            format!("{}:{}", temporary, name)
+        })
+        .collect();
+
+    // Remotes are pushed to. Since pushing to non-bare local repositories is
+    // not supported, we fetch from this repository into local targets.
+    let refspec_refs: Vec<&str> = refspecs.iter().map(String::as_str).collect();
+    let result = match repo.find_remote(target) {
+        Ok(mut remote) => {
+            let mut push_options = git2::PushOptions::new();
+            push_options.remote_callbacks(gitext::strict_push_callbacks());
+            remote.push(refspec_refs.as_ref(), Some(&mut push_options))
+        },
+        Err(_) => git2::Repository::open(target)
+            .and_then(|target_repo| {
+                // Unlike pushes, fetches may update references in a non
+                // fast-forward manner. We don't want to replace an issue.
+                let head_ref = format!("refs/dit/{}/head", transfer.issue_id());
+                if target_repo.find_reference(&head_ref).is_ok() {
+                    let msg = format!("{} already exists in {}", head_ref, target);
+                    return Err(git2::Error::from_str(&msg));
+                }
+
+                let mut fetch_options = git2::FetchOptions::new();
+                fetch_options.remote_callbacks(gitext::callbacks());
+                target_repo
+                    .remote_anonymous(&repo.path().to_string_lossy())?
+                    .fetch(refspec_refs.as_ref(), Some(&mut fetch_options), None)
+            }),
+    };
+
+    for refspec in refspecs.iter() {
+        let temporary = refspec.split(':').next().unwrap_or_default();
+        repo.find_reference(temporary)
+            .and_then(|mut reference| reference.delete())
+            .unwrap_or_abort();
+    }
+    result.unwrap_or_abort();
+
+    // leave a notice in the source, updating the head like the "tag"
+    // subcommand does
+    let mut head = repo.find_commit(head).unwrap_or_abort();
+    let message = [
+        head.reply_subject().unwrap_or_default(),
+        String::new(),
+        format!("This issue was moved to issue {} in {}.", transfer.issue_id(), target),
+        String::new(),
+        Trailer::new(MOVED_TO_SPEC.key, &format!("{} {}", target, transfer.issue_id())).to_string(),
+    ].to_vec().into_iter().collect_string();
+    let tree = libgitdit::attachment::message_tree(
+        &repo,
+        Some(&head.tree().unwrap_or_abort()),
+        Vec::new()
+    ).unwrap_or_abort();
+    util::create_message(&repo, MessageTarget::Head(transfer.issue()), &author, &committer, &message, &tree, &[&head])
         .unwrap_or_abort();
 
-    // update the head reference
-    issue.update_head(new, true).unwrap_or_abort();
+    println!("[dit][transfer] {}", transfer.issue_id());
+}
+
+
+/// tui subcommand implementation
+///
+#[cfg(feature = "tui")]
+fn tui_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+    let id_len = repo.abbreviation_length(matches);
+    let filter = matches
+        .values_of("filter")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+
+    tui::Tui::new(&repo, filter, id_len).run().unwrap_or_abort();
+}
+
+
+/// unwatch subcommand implementation
+///
+fn unwatch_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+
+    // NOTE: the issues are a required parameter
+    for issue in repo.cli_issues(matches).unwrap() {
+        notify::unwatch(&repo, issue.id()).unwrap_or_abort();
+    }
+}
+
+
+/// watch subcommand implementation
+///
+fn watch_impl(matches: &clap::ArgMatches) {
+    let repo = util::open_dit_repo();
+
+    match repo.cli_issues(matches) {
+        Some(issues) => for issue in issues {
+            notify::watch(&repo, issue.id()).unwrap_or_abort();
+        },
+        None => {
+            // list the issues watched
+            let mut watched: Vec<_> = notify::watched(&repo)
+                .unwrap_or_abort()
+                .into_iter()
+                .map(|id| id.to_string())
+                .collect();
+            watched.sort();
+            watched.into_iter().print_lines().unwrap_or_abort();
+        },
+    };
 }
 
 
@@ -753,14 +2089,46 @@ fn tag_impl(matches: &clap::ArgMatches) {
 ///
 /// Try to invoke an executable matching the name of the subcommand.
 ///
-fn handle_unknown_subcommand(name: &str, matches: &clap::ArgMatches) {
+fn handle_unknown_subcommand(name: &str, matches: &clap::ArgMatches, format: OutputFormat) {
+    let args: Vec<&str> = matches.values_of("").into_iter().flatten().collect();
+    run_external_subcommand(name, &args, format);
+}
+
+/// Run an external subcommand
+///
+/// The executable `git-dit-<name>` is invoked with the environment described
+/// by `libgitdit::plugin::PluginContext`. The issue is taken from the first
+/// argument which is the id of an issue, if any.
+///
+fn run_external_subcommand(name: &str, args: &[&str], format: OutputFormat) {
+    use libgitdit::plugin::{ISSUE_VAR, PluginContext, REPO_VAR};
     use std::process::Command;
 
+    let repo = git2::Repository::open_from_env().ok();
+    let context = PluginContext {
+        repo: repo.as_ref().map(|repo| repo.path().to_owned()),
+        // Only arguments resembling ids are considered as candidates.
+        issue: repo.as_ref().and_then(|repo| args
+            .iter()
+            .filter(|arg| arg.len() >= 4 && arg.chars().all(|c| c.is_ascii_hexdigit()))
+            .filter_map(|arg| util::resolve_issue(repo, arg).ok())
+            .map(|issue| issue.id())
+            .next()
+        ),
+        remote_prios: repo
+            .as_ref()
+            .map(|repo| repo.remote_priorization().remotes().to_vec())
+            .unwrap_or_else(|| vec!["*".to_owned()]),
+        format,
+    };
+
     // prepare the command to be invoked
     let mut command = Command::new(format!("git-dit-{}", name));
-    if let Some(values) = matches.values_of("") {
-         values.fold(&mut command, |c, arg| c.arg(arg));
-    }
+    command
+        .args(args)
+        .env_remove(REPO_VAR)
+        .env_remove(ISSUE_VAR)
+        .envs(context.vars());
 
     // run the command
     let result = command
@@ -773,9 +2141,74 @@ fn handle_unknown_subcommand(name: &str, matches: &clap::ArgMatches) {
 }
 
 
+/// Load the definition of the command line interface
+///
+/// Subcommands of optional features which are not enabled are removed from the
+/// definition.
+///
+fn cli_yaml() -> Yaml {
+    let mut disabled = Vec::new();
+    if cfg!(not(feature = "serve")) {
+        disabled.push("serve");
+    }
+    if cfg!(not(feature = "tui")) {
+        disabled.push("tui");
+    }
+
+    let mut yaml = load_yaml!("cli.yaml").clone();
+    if let Yaml::Hash(ref mut hash) = yaml {
+        if let Some(&mut Yaml::Array(ref mut subcommands)) = hash.get_mut(&Yaml::String("subcommands".to_owned())) {
+            subcommands.retain(|subcommand| subcommand
+                .as_hash()
+                .into_iter()
+                .flat_map(|subcommand| subcommand.keys())
+                .filter_map(|key| key.as_str())
+                .all(|name| !disabled.contains(&name))
+            );
+        }
+    }
+    yaml
+}
+
+
 fn main() {
-    let yaml    = load_yaml!("cli.yaml");
-    let matches = App::from_yaml(yaml).get_matches();
+    let yaml    = &cli_yaml();
+    let args: Vec<String> = std::env::args().collect();
+
+    // Help for external subcommands is provided by the subcommands themselves.
+    let is_builtin = |name: &str| yaml["subcommands"]
+        .as_vec()
+        .into_iter()
+        .flatten()
+        .filter_map(|subcommand| subcommand.as_hash())
+        .flat_map(|subcommand| subcommand.keys())
+        .any(|key| key.as_str() == Some(name));
+    if args.len() == 3 && args[1] == "help" && !is_builtin(&args[2]) {
+        run_external_subcommand(&args[2], &["--help"], OutputFormat::default());
+        return;
+    }
+
+    // List the external subcommands available along with the usual help.
+    let wants_help = args
+        .iter()
+        .skip(1)
+        .any(|arg| arg == "help" || arg == "-h" || arg == "--help");
+    let external = if wants_help {
+        let names = system::programs::external_subcommands();
+        if names.is_empty() {
+            String::new()
+        } else {
+            Some("EXTERNAL SUBCOMMANDS:".to_owned())
+                .into_iter()
+                .chain(names.into_iter().map(|name| format!("    {}", name)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    } else {
+        String::new()
+    };
+
+    let matches = App::from_yaml(yaml).after_help(external.as_str()).get_matches();
 
     if let Err(err) = system::Logger::init(Level::Warn) {
         writeln!(io::stderr(), "Could not initialize logger: {}", err).ok();
@@ -787,18 +2220,40 @@ fn main() {
         ("check-refname",               Some(sub_matches)) => check_refname(sub_matches),
         ("create-message",              Some(sub_matches)) => create_message(sub_matches),
         ("find-tree-init-hash",         Some(sub_matches)) => find_tree_init_hash(sub_matches),
+        ("get-completion-candidates",   Some(sub_matches)) => get_completion_candidates(sub_matches),
         ("get-issue-metadata",          Some(sub_matches)) => get_issue_metadata(sub_matches),
         ("get-issue-tree-init-hashes",  Some(sub_matches)) => get_issue_tree_init_hashes(sub_matches),
         // Porcelain subcommands
-        ("fetch",   Some(sub_matches)) => fetch_impl(sub_matches),
-        ("gc",      Some(sub_matches)) => gc_impl(sub_matches),
-        ("list",    Some(sub_matches)) => list_impl(sub_matches),
-        ("mirror",  Some(sub_matches)) => mirror_impl(sub_matches),
-        ("new",     Some(sub_matches)) => new_impl(sub_matches),
-        ("push",    Some(sub_matches)) => push_impl(sub_matches),
-        ("reply",   Some(sub_matches)) => reply_impl(sub_matches),
-        ("show",    Some(sub_matches)) => show_impl(sub_matches),
-        ("tag",     Some(sub_matches)) => tag_impl(sub_matches),
+        ("archive",     Some(sub_matches)) => archive_impl(sub_matches),
+        ("attachments", Some(sub_matches)) => attachments_impl(sub_matches),
+        ("changelog",   Some(sub_matches)) => changelog_impl(sub_matches),
+        ("completions", Some(sub_matches)) => completions_impl(sub_matches),
+        ("deps",        Some(sub_matches)) => deps_impl(sub_matches),
+        ("drafts",      Some(sub_matches)) => drafts_impl(sub_matches),
+        ("duplicate",   Some(sub_matches)) => duplicate_impl(sub_matches),
+        ("feed",        Some(sub_matches)) => feed_impl(sub_matches),
+        ("fetch",       Some(sub_matches)) => fetch_impl(sub_matches),
+        ("fixed-in",    Some(sub_matches)) => fixed_in_impl(sub_matches),
+        ("fsck",        Some(sub_matches)) => fsck_impl(sub_matches),
+        ("gc",          Some(sub_matches)) => gc_impl(sub_matches),
+        ("graph",       Some(sub_matches)) => graph_impl(sub_matches),
+        ("list",        Some(sub_matches)) => list_impl(sub_matches),
+        ("mark-read",   Some(sub_matches)) => mark_read_impl(sub_matches),
+        ("mirror",      Some(sub_matches)) => mirror_impl(sub_matches),
+        ("new",         Some(sub_matches)) => new_impl(sub_matches),
+        ("push",        Some(sub_matches)) => push_impl(sub_matches),
+        ("refs-to",     Some(sub_matches)) => refs_to_impl(sub_matches),
+        ("reply",       Some(sub_matches)) => reply_impl(sub_matches),
+        #[cfg(feature = "serve")]
+        ("serve",       Some(sub_matches)) => serve_impl(sub_matches),
+        ("show",        Some(sub_matches)) => show_impl(sub_matches),
+        ("split",       Some(sub_matches)) => split_impl(sub_matches),
+        ("tag",         Some(sub_matches)) => tag_impl(sub_matches),
+        ("transfer",    Some(sub_matches)) => transfer_impl(sub_matches),
+        #[cfg(feature = "tui")]
+        ("tui",         Some(sub_matches)) => tui_impl(sub_matches),
+        ("unwatch",     Some(sub_matches)) => unwatch_impl(sub_matches),
+        ("watch",       Some(sub_matches)) => watch_impl(sub_matches),
         // Unknown subcommands
         ("", _) => {
             writeln!(io::stderr(), "{}", matches.usage()).ok();
@@ -806,7 +2261,11 @@ fn main() {
         },
         (name, sub_matches) => {
             let default = clap::ArgMatches::default();
-            handle_unknown_subcommand(name, sub_matches.unwrap_or(&default))
+            let format = matches
+                .value_of("format")
+                .map(|format| format.parse().unwrap_or_abort())
+                .unwrap_or_default();
+            handle_unknown_subcommand(name, sub_matches.unwrap_or(&default), format)
         },
     }
 }
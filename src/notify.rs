@@ -0,0 +1,244 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Notifications
+//!
+//! This module provides subscriptions to issues and notifications about
+//! changes of issues. Issues are watched via `dit.watch` entries in the
+//! repository's configuration. For each relevant event, the command configured
+//! via `dit.notify.command` is run by the shell with a JSON payload on its
+//! standard input. The following events are considered relevant:
+//!
+//!  * `message`: a new message in a watched issue
+//!  * `status`: a change of the status of a watched issue
+//!  * `assignment`: a new message assigning an issue to the local user
+//!  * `mention`: a new message mentioning the local user's email address
+//!
+//! Messages authored by the local user never cause `message`, `assignment` or
+//! `mention` events.
+//!
+
+use git2::{Commit, ConfigLevel, Oid, Repository};
+use libgitdit::Message;
+use libgitdit::changes::{self, IssueChanges, RefUpdate};
+use libgitdit::message::block::Block;
+use libgitdit::trailer::spec::ASSIGNEE_SPEC;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use error::*;
+
+
+/// Configuration key for watched issues
+///
+const WATCH_KEY: &str = "dit.watch";
+
+
+/// Get the issues watched
+///
+pub fn watched(repo: &Repository) -> Result<HashSet<Oid>> {
+    let config = repo.config()?;
+    let entries = config.entries(Some(r"^dit\.watch$"))?;
+
+    let mut retval = HashSet::new();
+    for entry in &entries {
+        if let Some(id) = entry?.value().and_then(|value| Oid::from_str(value).ok()) {
+            retval.insert(id);
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Watch an issue
+///
+pub fn watch(repo: &Repository, issue: Oid) -> Result<()> {
+    if watched(repo)?.contains(&issue) {
+        return Ok(());
+    }
+    let id = issue.to_string();
+    repo.config()?
+        .open_level(ConfigLevel::Local)?
+        .set_multivar(WATCH_KEY, &format!("^{}$", id), &id)
+        .map_err(From::from)
+}
+
+
+/// Stop watching an issue
+///
+pub fn unwatch(repo: &Repository, issue: Oid) -> Result<()> {
+    if !watched(repo)?.contains(&issue) {
+        return Ok(());
+    }
+    repo.config()?
+        .open_level(ConfigLevel::Local)?
+        .remove_multivar(WATCH_KEY, &format!("^{}$", issue))
+        .map_err(From::from)
+}
+
+
+/// Notify about the changes caused by reference updates
+///
+/// The references are expected to be updated already.
+///
+pub fn notify_updates(repo: &Repository, updates: &[RefUpdate]) -> Result<()> {
+    if notify_command(repo)?.is_none() {
+        return Ok(());
+    }
+    let changes = changes::issue_changes(repo, updates)?;
+    notify(repo, &changes)
+}
+
+
+/// Notify about changes of issues
+///
+/// The notification command is run once for each relevant event. Failures of
+/// the command, including failures to run it at all, are reported as warnings.
+///
+pub fn notify(repo: &Repository, changes: &[IssueChanges]) -> Result<()> {
+    let command = match notify_command(repo)? {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+
+    for event in events(repo, changes)? {
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .stdin(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                // We still wait for the child if it does not consume the event.
+                let written = match child.stdin.take() {
+                    Some(mut stdin) => writeln!(stdin, "{}", event),
+                    None => Ok(()),
+                };
+                let status = child.wait()?;
+                written.map(|_| status)
+            });
+        match result {
+            Ok(ref status) if status.success() => {},
+            Ok(_) => warn!("Notification command failed: {}", command),
+            Err(err) => warn!("Could not run notification command {}: {}", command, err),
+        }
+    }
+    Ok(())
+}
+
+
+/// Get the notification command configured, if any
+///
+fn notify_command(repo: &Repository) -> Result<Option<String>> {
+    Ok(repo.config()?.get_string("dit.notify.command").ok())
+}
+
+
+/// Compute the events relevant to the local user
+///
+fn events(repo: &Repository, changes: &[IssueChanges]) -> Result<Vec<Value>> {
+    let watched = watched(repo)?;
+    let email = repo
+        .config()?
+        .get_string("user.email")
+        .ok()
+        .filter(|email| !email.is_empty())
+        .map(|email| email.to_lowercase());
+
+    let mut retval = Vec::new();
+    for change in changes {
+        let subject = repo
+            .find_commit(change.issue)
+            .ok()
+            .and_then(|message| message.summary().map(String::from));
+        let is_watched = watched.contains(&change.issue);
+
+        // The initial message of a new issue may also concern us.
+        let messages = Some(change.issue)
+            .filter(|_| change.is_new)
+            .into_iter()
+            .chain(change.new_messages.iter().cloned());
+        for id in messages {
+            let message = repo.find_commit(id)?;
+            let is_own = match (email.as_ref(), message.author().email()) {
+                (Some(email), Some(author)) => author.to_lowercase() == *email,
+                _ => false,
+            };
+            if is_own {
+                continue;
+            }
+
+            let payload = |event: &str| message_event(event, change.issue, &subject, &message);
+            if is_watched {
+                retval.push(payload("message"));
+            }
+
+            let email = match email {
+                Some(ref email) => email,
+                None => continue,
+            };
+            let is_assigned = message
+                .trailers()
+                .filter(|trailer| trailer.key.as_ref() == ASSIGNEE_SPEC.key)
+                .any(|trailer| trailer.value.to_string().to_lowercase().contains(email.as_str()));
+            if is_assigned {
+                retval.push(payload("assignment"));
+            }
+            let is_mentioned = message
+                .summary()
+                .map(String::from)
+                .into_iter()
+                .chain(message
+                    .body_blocks()
+                    .filter_map(|block| match block {
+                        Block::Text(lines) => Some(lines),
+                        _ => None,
+                    })
+                    .flatten()
+                )
+                .any(|line| line.to_lowercase().contains(email.as_str()));
+            if is_mentioned {
+                retval.push(payload("mention"));
+            }
+        }
+
+        if !is_watched {
+            continue;
+        }
+        for head in change.head_moves.iter().filter(|head| !head.old.is_zero()) {
+            if let Some((ref old, ref new)) = head.status {
+                retval.push(json!({
+                    "event": "status",
+                    "issue": change.issue.to_string(),
+                    "subject": subject,
+                    "ref": head.refname,
+                    "head": head.new.to_string(),
+                    "old": old,
+                    "new": new,
+                }));
+            }
+        }
+    }
+    Ok(retval)
+}
+
+
+/// Create the payload of an event concerning a message
+///
+fn message_event(event: &str, issue: Oid, subject: &Option<String>, message: &Commit) -> Value {
+    json!({
+        "event": event,
+        "issue": issue.to_string(),
+        "subject": subject,
+        "message": message.id().to_string(),
+        "author": message.author().to_string(),
+        "summary": message.summary(),
+    })
+}
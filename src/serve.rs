@@ -0,0 +1,543 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! HTTP server
+//!
+//! This module provides a small HTTP server exposing the issues of a
+//! repository via a JSON API and a minimal HTML interface. All responses are
+//! generated from the repository on each request. Requests are served one at a
+//! time.
+//!
+//! The following endpoints are provided:
+//!
+//!  * `GET /issues`: issues, optionally filtered via `filter` query parameters
+//!  * `GET /issues/<id>`: a single issue along with its metadata
+//!  * `GET /issues/<id>/messages`: the messages of an issue
+//!  * `POST /issues/<id>/messages`: add a reply to an issue (write mode only)
+//!  * `GET /` and `GET /view/<id>`: HTML interface
+//!
+//! Since users are not authenticated, replies are only accepted as JSON bodies
+//! of limited size from pages served by the server itself. Requests addressing
+//! the server under another host, e.g. via DNS rebinding, are rejected.
+//!
+
+use git2::{Commit, Oid, Repository};
+use libgitdit::{Issue, Message, RepositoryExt};
+use libgitdit::message::LineIteratorExt;
+use serde_json::{self, Value};
+use std::cmp::Reverse;
+use std::io::{Cursor, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use tiny_http::{self, Header, Method, Request, Response};
+
+use display::{escape, rfc3339};
+use error::*;
+use error::ErrorKind as EK;
+use filters::{self, FilterSpec, MetadataFilter};
+use gitext::{RemotePriorization, ReferrencesExt};
+use util::{self, MessageTarget};
+
+
+/// Maximum size of a request body in bytes
+///
+const MAX_BODY_SIZE: u64 = 1 << 20;
+
+
+/// Form for adding replies via the JSON API
+///
+const REPLY_FORM: &str = r#"<h3>Reply</h3>
+<form id="reply">
+<p><input name="parent" size="42" value="{parent}"> (parent)</p>
+<p><textarea name="message" rows="12" cols="72"></textarea></p>
+<p><button>Send</button></p>
+</form>
+<script>
+document.getElementById("reply").onsubmit = function(event) {
+    event.preventDefault();
+    var form = event.target;
+    fetch("/issues/{issue}/messages", {
+        method: "POST",
+        headers: {"Content-Type": "application/json"},
+        body: JSON.stringify({parent: form.parent.value, message: form.message.value})
+    }).then(function(response) {
+        return response.json().then(function(data) {
+            if (response.ok) { location.reload(); } else { alert(data.error); }
+        });
+    });
+};
+</script>
+"#;
+
+
+/// Response to a request
+///
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: u16, value: Value) -> Self {
+        Reply { status, content_type: "application/json", body: value.to_string() }
+    }
+
+    fn html(body: String) -> Self {
+        Reply { status: 200, content_type: "text/html; charset=utf-8", body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({"error": message}))
+    }
+
+    fn not_found() -> Self {
+        Self::error(404, "Not found")
+    }
+
+    fn into_response(self) -> Response<Cursor<Vec<u8>>> {
+        let header = Header::from_bytes(&b"Content-Type"[..], self.content_type.as_bytes())
+            .expect("Invalid content type header");
+        Response::from_string(self.body)
+            .with_status_code(self.status)
+            .with_header(header)
+    }
+}
+
+
+/// HTTP server exposing the issues of a repository
+///
+pub struct Server<'r> {
+    repo: &'r Repository,
+    prios: RemotePriorization,
+    writable: bool,
+}
+
+impl<'r> Server<'r> {
+    /// Create a new server for a repository
+    ///
+    /// If `writable` is set, replies may be added to issues.
+    ///
+    pub fn new(repo: &'r Repository, writable: bool) -> Self {
+        use util::RepositoryUtil;
+
+        Server { repo, prios: repo.remote_priorization(), writable }
+    }
+
+    /// Serve requests on the address supplied
+    ///
+    /// This function only returns if the server could not be started.
+    ///
+    pub fn run(&self, addr: &str) -> Result<()> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|err| Error::from_kind(EK::CannotStartServer(format!("{}: {}", addr, err))))?;
+        println!("[dit][serve] http://{}/", addr);
+
+        for mut request in server.incoming_requests() {
+            let reply = self.handle(&mut request, addr).unwrap_or_else(|err| match *err.kind() {
+                EK::MalformedFilterSpec(_) => Reply::error(400, &err.to_string()),
+                EK::HookRejected(_) => Reply::error(403, &err.to_string()),
+                _ => {
+                    warn!("Could not serve {}: {}", request.url(), err);
+                    Reply::error(500, &err.to_string())
+                },
+            });
+            if let Err(err) = request.respond(reply.into_response()) {
+                warn!("Could not send response: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a single request
+    ///
+    /// The address supplied is the one the server listens on.
+    ///
+    fn handle(&self, request: &mut Request, addr: &str) -> Result<Reply> {
+        let url = request.url().to_owned();
+        let (path, query) = match url.find('?') {
+            Some(pos) => (&url[..pos], &url[pos + 1..]),
+            None => (url.as_ref(), ""),
+        };
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (request.method(), segments.as_slice()) {
+            (&Method::Get, []) => self.index_html(query),
+            (&Method::Get, ["view", id]) => self.with_issue(id, |issue| self.issue_html(issue)),
+            (&Method::Get, ["issues"]) => self.issues_json(query),
+            (&Method::Get, ["issues", id]) => self.with_issue(id, |issue| self.issue_json(issue)),
+            (&Method::Get, ["issues", id, "messages"]) => self.with_issue(id, |issue| self.messages_json(issue)),
+            (&Method::Post, ["issues", id, "messages"]) => {
+                if !self.writable {
+                    return Ok(Reply::error(403, "Server is read-only"));
+                }
+                if !is_same_origin(request, addr) {
+                    return Ok(Reply::error(403, "Cross-origin requests are not allowed"));
+                }
+                if !is_json(request) {
+                    return Ok(Reply::error(415, "Expected a JSON body"));
+                }
+                if request.body_length().map(|len| len as u64 > MAX_BODY_SIZE).unwrap_or(false) {
+                    return Ok(Reply::error(413, "Request body too large"));
+                }
+                let mut body = Vec::new();
+                request
+                    .as_reader()
+                    .take(MAX_BODY_SIZE + 1)
+                    .read_to_end(&mut body)
+                    .chain_err(|| EK::WrappedIOError)?;
+                if body.len() as u64 > MAX_BODY_SIZE {
+                    return Ok(Reply::error(413, "Request body too large"));
+                }
+                let body: Value = match serde_json::from_slice(&body) {
+                    Ok(body) => body,
+                    Err(err) => return Ok(Reply::error(400, &err.to_string())),
+                };
+                self.with_issue(id, |issue| self.add_reply(issue, &body))
+            },
+            (_, ["issues", ..]) => Ok(Reply::error(405, "Method not allowed")),
+            _ => Ok(Reply::not_found()),
+        }
+    }
+
+    /// Call a function with the issue with the id supplied
+    ///
+    /// If the issue does not exist, a "not found" reply is returned.
+    ///
+    fn with_issue<F>(&self, id: &str, func: F) -> Result<Reply>
+        where F: FnOnce(&Issue<'r>) -> Result<Reply>
+    {
+        match Oid::from_str(id).ok().and_then(|id| self.repo.find_issue(id).ok()) {
+            Some(issue) => func(&issue),
+            None => Ok(Reply::not_found()),
+        }
+    }
+
+    /// Get the issues matching the filter specs in a query string
+    ///
+    /// The issues are ordered by the time of their initial message, newest
+    /// first.
+    ///
+    fn issues(&self, query: &str) -> Result<Vec<Issue<'r>>> {
+        let mut specs = query_values(query, "filter")
+            .iter()
+            .map(|spec| spec.parse())
+            .collect::<Result<Vec<FilterSpec>>>()?;
+
+        // Like the "list" subcommand, we hide duplicates unless filtering for
+        // a status.
+        filters::hide_duplicates(&mut specs);
+        let filter = MetadataFilter::new(&self.prios, specs)?;
+
+        let mut issues: Vec<_> = self
+            .repo
+            .issues()?
+            .into_iter()
+            .filter(|issue| !issue.is_archived())
+            .filter(|issue| filter.filter(issue))
+            .collect();
+        issues.sort_by_key(|issue| Reverse(issue
+            .initial_message()
+            .map(|message| message.time().seconds())
+            .unwrap_or_default()
+        ));
+        Ok(issues)
+    }
+
+    /// Get the head of an issue
+    ///
+    fn head(&self, issue: &Issue<'r>) -> Result<Option<Commit<'r>>> {
+        let head = issue
+            .heads()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?
+            .select_ref(&self.prios);
+        match head {
+            Some(head) => Ok(Some(head.peel_to_commit()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the messages of an issue in chronological order
+    ///
+    fn messages(&self, issue: &Issue<'r>) -> Result<Vec<Commit<'r>>> {
+        let mut messages = issue
+            .messages()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        // Messages are returned with replies preceding their parents. We
+        // reverse them first since the sort is stable and times may be equal.
+        messages.reverse();
+        messages.sort_by_key(|message| message.time());
+        Ok(messages)
+    }
+
+    /// Represent an issue as a JSON object
+    ///
+    fn issue_value(&self, issue: &Issue<'r>) -> Result<Value> {
+        let initial = issue.initial_message()?;
+        let head = self.head(issue)?;
+        let metadata: serde_json::Map<String, Value> = util::issue_metadata(issue, &self.prios)?
+            .into_iter()
+            .map(|(key, values)| (key, json!(values)))
+            .collect();
+
+        Ok(json!({
+            "id": issue.id().to_string(),
+            "subject": initial.summary(),
+            "author": signature_value(&initial.author()),
+            "date": rfc3339(initial.author().when()),
+            "head": head.map(|head| head.id().to_string()),
+            "metadata": metadata,
+        }))
+    }
+
+    /// Represent a message as a JSON object
+    ///
+    fn message_value(&self, message: &Commit) -> Value {
+        let trailers: Vec<Value> = message
+            .trailers()
+            .map(|trailer| json!({"key": trailer.key.to_string(), "value": trailer.value.to_string()}))
+            .collect();
+
+        json!({
+            "id": message.id().to_string(),
+            "parents": message.parent_ids().map(|id| id.to_string()).collect::<Vec<_>>(),
+            "author": signature_value(&message.author()),
+            "date": rfc3339(message.author().when()),
+            "subject": message.summary(),
+            "body": message.body_lines().collect_string().trim(),
+            "trailers": trailers,
+        })
+    }
+
+    fn issues_json(&self, query: &str) -> Result<Reply> {
+        let issues = self.issues(query)?;
+        let values = issues
+            .iter()
+            .map(|issue| self.issue_value(issue))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Reply::json(200, Value::Array(values)))
+    }
+
+    fn issue_json(&self, issue: &Issue<'r>) -> Result<Reply> {
+        self.issue_value(issue).map(|value| Reply::json(200, value))
+    }
+
+    fn messages_json(&self, issue: &Issue<'r>) -> Result<Reply> {
+        let values = self
+            .messages(issue)?
+            .iter()
+            .map(|message| self.message_value(message))
+            .collect();
+        Ok(Reply::json(200, Value::Array(values)))
+    }
+
+    /// Add a reply to an issue
+    ///
+    /// The request body is expected to be a JSON object with a "message" and
+    /// an optional "parent". The reply is added to the parent or, if no parent
+    /// is supplied, the issue's head.
+    ///
+    fn add_reply(&self, issue: &Issue<'r>, body: &Value) -> Result<Reply> {
+        let text = match body.get("message").and_then(Value::as_str) {
+            Some(text) => text,
+            None => return Ok(Reply::error(400, "Missing message")),
+        };
+        let lines: Vec<String> = text.lines().stripped().collect();
+        if let Err(err) = lines.iter().check_message_format() {
+            return Ok(Reply::error(400, &err.to_string()));
+        }
+        let message = lines.into_iter().collect_string();
+
+        let parent = match body.get("parent").and_then(Value::as_str) {
+            Some(id) => Oid::from_str(id).ok().and_then(|id| self.repo.find_commit(id).ok()),
+            None => self.head(issue)?,
+        };
+        let parent = match parent {
+            Some(ref parent) if self.repo.issue_with_message(parent).map(|i| i == *issue).unwrap_or(false) => parent.clone(),
+            _ => return Ok(Reply::error(400, "Parent is not a message of the issue")),
+        };
+
+        let sig = self.repo.signature()?;
+        let tree = self.repo.empty_tree()?;
+        let reply = util::create_message(self.repo, MessageTarget::Leaf(issue), &sig, &sig, &message, &tree, &[&parent])?;
+        Ok(Reply::json(201, json!({"id": reply.to_string()})))
+    }
+
+    /// Render the list of issues as HTML
+    ///
+    fn index_html(&self, query: &str) -> Result<Reply> {
+        let filter = query_values(query, "filter").join(" ");
+        let issues = self.issues(query)?;
+
+        let mut body = String::new();
+        body.push_str("<form method=\"get\" action=\"/\">");
+        body.push_str(&format!(
+            "<input name=\"filter\" value=\"{}\" placeholder=\"status=open\"> <button>Filter</button>",
+            escape(&filter)
+        ));
+        body.push_str("</form>\n<table>\n");
+        for issue in issues.iter() {
+            let initial = issue.initial_message()?;
+            body.push_str(&format!(
+                "<tr><td><a href=\"/view/{0}\"><code>{1:.8}</code></a></td><td>{2}</td><td>{3}</td></tr>\n",
+                issue.id(),
+                issue.id().to_string(),
+                escape(&util::issue_summary(issue, &self.prios)),
+                escape(initial.author().name().unwrap_or_default()),
+            ));
+        }
+        body.push_str("</table>\n");
+        Ok(Reply::html(page("Issues", &body)))
+    }
+
+    /// Render an issue as HTML
+    ///
+    fn issue_html(&self, issue: &Issue<'r>) -> Result<Reply> {
+        let initial = issue.initial_message()?;
+        let head = self.head(issue)?.map(|head| head.id());
+        let subject = initial.summary().unwrap_or_default().to_owned();
+
+        let mut body = String::from("<p><a href=\"/\">All issues</a></p>\n<dl>\n");
+        for (key, values) in util::issue_metadata(issue, &self.prios)? {
+            body.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", escape(&key), escape(&values.join(", "))));
+        }
+        body.push_str("</dl>\n");
+
+        for message in self.messages(issue)? {
+            let marker = if Some(message.id()) == head { " (head)" } else { "" };
+            body.push_str(&format!(
+                "<div class=\"message\" id=\"{0}\"><p><code>{0}</code>{1}<br>{2} &lt;{3}&gt;, {4}</p><h3>{5}</h3><pre>{6}</pre></div>\n",
+                message.id(),
+                marker,
+                escape(message.author().name().unwrap_or_default()),
+                escape(message.author().email().unwrap_or_default()),
+                rfc3339(message.author().when()),
+                escape(message.summary().unwrap_or_default()),
+                escape(message.body_lines().collect_string().trim()),
+            ));
+        }
+
+        if self.writable {
+            body.push_str(&REPLY_FORM
+                .replace("{issue}", &issue.id().to_string())
+                .replace("{parent}", &head.map(|id| id.to_string()).unwrap_or_default()));
+        }
+        Ok(Reply::html(page(&subject, &body)))
+    }
+}
+
+
+/// Wrap a body into an HTML page
+///
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n{1}</body></html>\n",
+        escape(title),
+        body
+    )
+}
+
+
+/// Represent a signature as a JSON object
+///
+fn signature_value(signature: &::git2::Signature) -> Value {
+    json!({"name": signature.name(), "email": signature.email()})
+}
+
+
+/// Get the value of a request's header
+///
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+
+/// Check whether a request's body is declared to be JSON
+///
+fn is_json(request: &Request) -> bool {
+    header(request, "Content-Type")
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().eq_ignore_ascii_case("application/json"))
+        .unwrap_or(false)
+}
+
+
+/// Check whether a request originates from a page served by the server
+///
+/// The host addressed by the request needs to match the address the server
+/// listens on. If the server listens on all interfaces, only the port needs to
+/// match. If the request carries an origin, it needs to match the host.
+///
+fn is_same_origin(request: &Request, addr: &str) -> bool {
+    let host = match header(request, "Host") {
+        Some(host) => host,
+        None => return false,
+    };
+
+    let listening = addr.to_socket_addrs().ok().and_then(|mut addrs| addrs.next());
+    let host_matches = host.eq_ignore_ascii_case(addr) || match listening {
+        Some(listening) if listening.ip().is_unspecified() => host
+            .rsplit(':')
+            .next()
+            .and_then(|port| port.parse::<u16>().ok()) == Some(listening.port()),
+        Some(listening) => host.parse::<SocketAddr>().ok() == Some(listening),
+        None => false,
+    };
+
+    host_matches && header(request, "Origin")
+        .map(|origin| origin.eq_ignore_ascii_case(&format!("http://{}", host)))
+        .unwrap_or(true)
+}
+
+
+/// Get all values of a parameter from a query string
+///
+/// The values are percent-decoded, with '+' decoding to a space.
+///
+fn query_values(query: &str, name: &str) -> Vec<String> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if percent_decode(key) == name => Some(percent_decode(value)),
+                _ => None,
+            }
+        })
+        .flat_map(|value| value.split_whitespace().map(String::from).collect::<Vec<_>>())
+        .collect()
+}
+
+
+/// Decode a percent-encoded string
+///
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut input = text.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = input.by_ref().take(2).collect();
+                match ::std::str::from_utf8(&hex).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(decoded) => bytes.push(decoded),
+                    None => {
+                        bytes.push(b'%');
+                        bytes.extend(hex);
+                    },
+                }
+            },
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -7,7 +7,8 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 //
 
-use std::env::var as env_var;
+use std::env::{split_paths, var as env_var, var_os};
+use std::fs;
 use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
 
@@ -105,3 +106,29 @@ pub fn pager(config: Config) -> Result<Child> {
         })
 }
 
+
+/// Find the names of external subcommands
+///
+/// External subcommands are executables named `git-dit-<name>` in any of the
+/// directories listed in `PATH`. This function returns the names, sorted and
+/// without duplicates.
+///
+pub fn external_subcommands() -> Vec<String> {
+    let mut retval: Vec<String> = var_os("PATH")
+        .map(|paths| split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("git-dit-"))
+            .map(String::from)
+        )
+        .collect();
+    retval.sort();
+    retval.dedup();
+    retval
+}
@@ -0,0 +1,502 @@
+//   git-dit - the distributed issue tracker for git
+//   Copyright (C) 2017 Matthias Beyer <mail@beyermatthias.de>
+//   Copyright (C) 2017 Julian Ganz <neither@nut.email>
+//
+//   This program is free software; you can redistribute it and/or modify
+//   it under the terms of the GNU General Public License version 2 as
+//   published by the Free Software Foundation.
+//
+
+//! Interactive terminal interface
+//!
+//! This module provides a full-screen interface for browsing and triaging
+//! issues. The screen is divided into a list of issues, a thread pane
+//! displaying the message tree of the selected issue followed by the selected
+//! message and a status line. Replies are composed by running the "reply"
+//! subcommand with the interface suspended.
+//!
+
+use chrono::format::strftime::StrftimeItems;
+use git2::{Commit, Oid, Repository};
+use libgitdit::{Issue, RepositoryExt};
+use libgitdit::error::Error as GitDitError;
+use libgitdit::error::ErrorKind as GitDitErrorKind;
+use libgitdit::trailer::Trailer;
+use libgitdit::trailer::spec::{ASSIGNEE_SPEC, ISSUE_STATUS_SPEC};
+use std::cmp::Reverse;
+use std::env;
+use std::io::{self, Write};
+use std::process::Command;
+use termion::{clear, cursor, style, terminal_size};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::{AlternateScreen, ToAlternateScreen, ToMainScreen};
+
+use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
+use display::{IntoTreeGraph, TreeGraphElem};
+use error::*;
+use error::ErrorKind as EK;
+use filters::{self, FilterSpec, MetadataFilter};
+use gitext::{RemotePriorization, ReferrencesExt};
+use hooks;
+use util;
+
+
+type Screen = AlternateScreen<RawTerminal<io::Stdout>>;
+
+
+/// Key bindings displayed in the status line by default
+///
+const HELP: &str = "j/k: move  tab: switch pane  /: filter  r: reply  s: status  a: assignee  h: move head  g: reload  q: quit";
+
+
+/// Panes which may be focused
+///
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Issues,
+    Thread,
+}
+
+
+/// State of the terminal interface
+///
+pub struct Tui<'r> {
+    repo: &'r Repository,
+    prios: RemotePriorization,
+    id_len: usize,
+    /// Filter specifications, as entered by the user
+    filter: Vec<String>,
+    /// Issues listed along with the lines representing them
+    issues: Vec<(Issue<'r>, String)>,
+    selected_issue: usize,
+    /// Messages of the selected issue along with the tree lines representing
+    /// them
+    thread: Vec<(Commit<'r>, String)>,
+    selected_message: usize,
+    focus: Pane,
+    status: String,
+}
+
+impl<'r> Tui<'r> {
+    /// Create a new interface for a repository
+    ///
+    /// Issues are filtered using the filter specifications supplied.
+    ///
+    pub fn new(repo: &'r Repository, filter: Vec<String>, id_len: usize) -> Self {
+        use util::RepositoryUtil;
+
+        Tui {
+            repo,
+            prios: repo.remote_priorization(),
+            id_len,
+            filter,
+            issues: Vec::new(),
+            selected_issue: 0,
+            thread: Vec::new(),
+            selected_message: 0,
+            focus: Pane::Issues,
+            status: HELP.to_owned(),
+        }
+    }
+
+    /// Run the interface until the user quits
+    ///
+    pub fn run(mut self) -> Result<()> {
+        self.reload();
+
+        let mut screen = io::stdout()
+            .into_raw_mode()
+            .map(AlternateScreen::from)
+            .chain_err(|| EK::WrappedIOError)?;
+        let result = self.event_loop(&mut screen);
+        write!(screen, "{}", cursor::Show)
+            .and_then(|_| screen.flush())
+            .chain_err(|| EK::WrappedIOError)?;
+        result
+    }
+
+    /// Process keys until the user quits
+    ///
+    fn event_loop(&mut self, screen: &mut Screen) -> Result<()> {
+        self.draw(screen)?;
+        let stdin = io::stdin();
+        let mut keys = stdin.lock().keys();
+        while let Some(key) = keys.next() {
+            match key.chain_err(|| EK::WrappedIOError)? {
+                Key::Char('q') | Key::Esc => break,
+                Key::Char('j') | Key::Down => self.move_selection(1),
+                Key::Char('k') | Key::Up => self.move_selection(-1),
+                Key::Char('\t') | Key::Char('\n') | Key::BackTab => {
+                    self.focus = match self.focus {
+                        Pane::Issues => Pane::Thread,
+                        Pane::Thread => Pane::Issues,
+                    };
+                },
+                Key::Char('g') => self.reload(),
+                Key::Char('/') => {
+                    let current = self.filter.join(" ");
+                    if let Some(spec) = self.prompt(screen, &mut keys, "Filter: ", &current)? {
+                        self.filter = spec.split_whitespace().map(String::from).collect();
+                        self.selected_issue = 0;
+                        self.reload();
+                    }
+                },
+                Key::Char('r') => self.reply(screen)?,
+                Key::Char('s') => {
+                    if let Some(status) = self.prompt(screen, &mut keys, "Status: ", "")? {
+                        self.tag(ISSUE_STATUS_SPEC.key, &status);
+                    }
+                },
+                Key::Char('a') => {
+                    if let Some(assignee) = self.prompt(screen, &mut keys, "Assignee: ", "")? {
+                        self.tag(ASSIGNEE_SPEC.key, &assignee);
+                    }
+                },
+                Key::Char('h') => self.move_head(),
+                _ => self.status = HELP.to_owned(),
+            }
+            self.draw(screen)?;
+        }
+        Ok(())
+    }
+
+    /// Reload the issues and the thread of the selected issue
+    ///
+    /// Errors are reported in the status line.
+    ///
+    fn reload(&mut self) {
+        if let Err(err) = self.load_issues().and_then(|_| self.load_thread()) {
+            self.status = format!("Error: {}", err);
+        }
+    }
+
+    /// Load the issues matching the filter
+    ///
+    fn load_issues(&mut self) -> Result<()> {
+        let mut specs = self
+            .filter
+            .iter()
+            .map(|spec| spec.parse())
+            .collect::<Result<Vec<FilterSpec>>>()?;
+
+        // Like the "list" subcommand, we hide duplicates unless filtering for
+        // a status.
+        filters::hide_duplicates(&mut specs);
+        let filter = MetadataFilter::new(&self.prios, specs)?;
+
+        let mut issues: Vec<_> = self
+            .repo
+            .issues()?
+            .into_iter()
+            .filter(|issue| !issue.is_archived())
+            .filter(|issue| filter.filter(issue))
+            .collect();
+        issues.sort_by_key(|issue| Reverse(issue
+            .initial_message()
+            .map(|message| message.time().seconds())
+            .unwrap_or_default()
+        ));
+
+        self.issues = issues
+            .into_iter()
+            .map(|issue| {
+                let line = format!("{0:.1$} {2}", issue.id(), self.id_len, util::issue_summary(&issue, &self.prios));
+                (issue, line)
+            })
+            .collect();
+        self.selected_issue = self.selected_issue.min(self.issues.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Load the thread of the selected issue
+    ///
+    fn load_thread(&mut self) -> Result<()> {
+        self.thread.clear();
+        self.selected_message = 0;
+        let issue = match self.issues.get(self.selected_issue) {
+            Some((issue, _)) => issue,
+            None => return Ok(()),
+        };
+
+        let head = self.head(issue)?.id();
+        let formatter : Vec<FT<_,_>> = tokenvec![
+            MFT::Id(self.id_len), MFT::IfId(head, tokenvec![" (head)"]),
+            " ", MFT::AuthorName, " ", MFT::Subject];
+
+        // We want the messages in chronological order
+        let mut messages: Vec<_> = issue
+            .messages()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?
+            .into_iter()
+            .into_tree_graph()
+            .collect();
+        messages.reverse();
+        for (mut elems, message) in messages {
+            elems.reverse_marks();
+            elems.append(TreeGraphElem::Empty);
+            let graph = elems
+                .commit_iterator()
+                .next()
+                .map(|line| line.to_string())
+                .unwrap_or_default();
+            let text = formatter
+                .iter()
+                .formatted_lines(&message)
+                .next()
+                .unwrap_or_else(|| Ok(String::new()))?;
+            self.thread.push((message, format!("{} {}", graph, text)));
+        }
+        Ok(())
+    }
+
+    /// Get the selected head of an issue
+    ///
+    fn head(&self, issue: &Issue<'r>) -> Result<Commit<'r>> {
+        issue
+            .heads()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?
+            .select_ref(&self.prios)
+            .ok_or_else(|| GitDitError::from_kind(GitDitErrorKind::CannotFindIssueHead(issue.id())).into())
+            .and_then(|head| head.peel_to_commit().map_err(From::from))
+    }
+
+    /// Move the selection in the focused pane
+    ///
+    fn move_selection(&mut self, offset: isize) {
+        let (selected, len) = match self.focus {
+            Pane::Issues => (&mut self.selected_issue, self.issues.len()),
+            Pane::Thread => (&mut self.selected_message, self.thread.len()),
+        };
+        let new = (*selected as isize + offset).max(0).min(len as isize - 1).max(0) as usize;
+        if new == *selected {
+            return;
+        }
+        *selected = new;
+
+        if self.focus == Pane::Issues {
+            if let Err(err) = self.load_thread() {
+                self.status = format!("Error: {}", err);
+            }
+        }
+    }
+
+    /// Get the message selected in the thread pane
+    ///
+    fn selected_message(&self) -> Option<&Commit<'r>> {
+        self.thread.get(self.selected_message).map(|(message, _)| message)
+    }
+
+    /// Reply to the selected message
+    ///
+    /// The interface is suspended while the "reply" subcommand is running.
+    ///
+    fn reply(&mut self, screen: &mut Screen) -> Result<()> {
+        let id = match self.selected_message() {
+            Some(message) => message.id(),
+            None => return Ok(()),
+        };
+
+        let status = suspended(screen, || env::current_exe()
+            .and_then(|exe| Command::new(exe).arg("reply").arg(id.to_string()).status())
+        ).chain_err(|| EK::WrappedIOError)?;
+
+        self.status = if status.success() {
+            format!("Replied to {}", id)
+        } else {
+            "Reply aborted".to_owned()
+        };
+        let selected_issue = self.selected_issue;
+        self.reload();
+        self.selected_issue = selected_issue;
+        Ok(())
+    }
+
+    /// Set a piece of metadata of the selected issue
+    ///
+    /// A message carrying the metadata is added to the issue and becomes the
+    /// issue's head.
+    ///
+    fn tag(&mut self, key: &str, value: &str) {
+        let result = match self.issues.get(self.selected_issue) {
+            Some((issue, _)) => self.tag_issue(issue, key, value),
+            None => return,
+        };
+        self.status = match result {
+            Ok(id) => format!("Set {} to \"{}\" in {}", key, value, id),
+            Err(err) => format!("Error: {}", err),
+        };
+        self.reload();
+    }
+
+    fn tag_issue(&self, issue: &Issue<'r>, key: &str, value: &str) -> Result<Oid> {
+        let mut head = self.head(issue)?;
+        let signature = self.repo.signature()?;
+        let trailers = vec![Trailer::new(key, value)];
+        util::tag_issue(issue, &mut head, &signature, &signature, trailers, &[])
+    }
+
+    /// Make the selected message the head of the selected issue
+    ///
+    fn move_head(&mut self) {
+        let result = match (self.issues.get(self.selected_issue), self.selected_message()) {
+            (Some((issue, _)), Some(message)) => self.update_head(issue, message.id())
+                .map(|_| format!("Moved head to {}", message.id())),
+            _ => return,
+        };
+        self.status = result.unwrap_or_else(|err| format!("Error: {}", err));
+        self.reload();
+    }
+
+    /// Update the local head of an issue, subject to the dit hooks
+    ///
+    fn update_head(&self, issue: &Issue<'r>, message: Oid) -> Result<()> {
+        let refname = format!("refs/dit/{}/head", issue.id());
+        let old = issue
+            .local_head()
+            .ok()
+            .and_then(|head| head.target())
+            .unwrap_or_else(Oid::zero);
+        hooks::pre_head_update(self.repo, issue.id(), &refname, old, message)?;
+        issue.update_head(message, true)?;
+        Ok(())
+    }
+
+    /// Prompt the user for a line of text in the status line
+    ///
+    /// Returns `None` if the user aborted the input via escape.
+    ///
+    fn prompt<I>(&mut self, screen: &mut Screen, keys: &mut I, label: &str, initial: &str) -> Result<Option<String>>
+        where I: Iterator<Item = io::Result<Key>>
+    {
+        let mut input = initial.to_owned();
+        loop {
+            self.status = format!("{}{}", label, input);
+            self.draw(screen)?;
+            match keys.next() {
+                Some(key) => match key.chain_err(|| EK::WrappedIOError)? {
+                    Key::Char('\n') => break,
+                    Key::Esc => {
+                        self.status = HELP.to_owned();
+                        return Ok(None);
+                    },
+                    Key::Backspace => { input.pop(); },
+                    Key::Char(c) => input.push(c),
+                    _ => {},
+                },
+                None => return Ok(None),
+            }
+        }
+        self.status = HELP.to_owned();
+        Ok(Some(input))
+    }
+
+    /// Draw the whole screen
+    ///
+    fn draw(&self, screen: &mut Screen) -> Result<()> {
+        // The thread pane displays the selected message below the tree.
+        let mut details = Vec::new();
+        if let Some(message) = self.selected_message() {
+            let formatter : Vec<FT<_,_>> = tokenvec![
+                "Author: ", MFT::Author, FT::LineEnd,
+                "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
+                FT::LineEnd,
+                MFT::Subject, FT::LineEnd,
+                FT::LineEnd,
+                MFT::Body];
+            details.push(String::new());
+            for line in formatter.iter().formatted_lines(message) {
+                details.push(line?);
+            }
+        }
+
+        self.render(screen, &details).chain_err(|| EK::WrappedIOError)
+    }
+
+    /// Render the panes and the status line
+    ///
+    fn render(&self, screen: &mut Screen, details: &[String]) -> io::Result<()> {
+        let (width, height) = terminal_size()?;
+        let (width, height) = (width as usize, height as usize);
+        let list_height = (height / 3).max(3);
+        let thread_height = height.saturating_sub(list_height + 2);
+
+        write!(screen, "{}{}", clear::All, cursor::Hide)?;
+
+        // issue list
+        let lines: Vec<&str> = self.issues.iter().map(|(_, line)| line.as_ref()).collect();
+        let selected = if self.focus == Pane::Issues { Some(self.selected_issue) } else { None };
+        draw_lines(screen, 1, list_height, width, &lines, self.selected_issue, selected)?;
+
+        // separator
+        let title = format!("-- {} issues, filter: {} ", self.issues.len(), self.filter.join(" "));
+        write!(screen, "{}{}{}", cursor::Goto(1, list_height as u16 + 1), style::Invert, truncated(&title, width))?;
+        write!(screen, "{}{}", "-".repeat(width.saturating_sub(title.chars().count())), style::Reset)?;
+
+        // thread pane
+        let lines: Vec<&str> = self
+            .thread
+            .iter()
+            .map(|(_, line)| line.as_ref())
+            .chain(details.iter().map(AsRef::as_ref))
+            .collect();
+        let selected = if self.focus == Pane::Thread { Some(self.selected_message) } else { None };
+        draw_lines(screen, list_height + 2, thread_height, width, &lines, self.selected_message, selected)?;
+
+        // status line
+        write!(screen, "{}{}", cursor::Goto(1, height as u16), truncated(&self.status, width))?;
+        screen.flush()
+    }
+}
+
+
+/// Run a function with the interface suspended
+///
+/// The main screen is restored and raw mode is left while the function is
+/// running, e.g. for running an editor.
+///
+fn suspended<F, T>(screen: &mut Screen, func: F) -> io::Result<T>
+    where F: FnOnce() -> io::Result<T>
+{
+    write!(screen, "{}{}", ToMainScreen, cursor::Show)?;
+    screen.flush()?;
+    screen.suspend_raw_mode()?;
+    let result = func();
+    screen.activate_raw_mode()?;
+    write!(screen, "{}", ToAlternateScreen)?;
+    result
+}
+
+
+/// Draw lines into a region of the screen
+///
+/// The lines are scrolled such that the line at the position supplied is
+/// visible. The line selected, if any, is highlighted.
+///
+fn draw_lines(screen: &mut Screen,
+              top: usize,
+              height: usize,
+              width: usize,
+              lines: &[&str],
+              position: usize,
+              selected: Option<usize>
+) -> io::Result<()> {
+    let offset = (position + 1).saturating_sub(height);
+    for (row, (number, line)) in lines.iter().enumerate().skip(offset).take(height).enumerate() {
+        write!(screen, "{}", cursor::Goto(1, (top + row) as u16))?;
+        if selected == Some(number) {
+            write!(screen, "{}{}{}", style::Invert, truncated(line, width), style::Reset)?;
+        } else {
+            write!(screen, "{}", truncated(line, width))?;
+        }
+    }
+    Ok(())
+}
+
+
+/// Truncate a line to a width
+///
+fn truncated(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
@@ -10,20 +10,31 @@
 use clap::{ArgMatches, Values};
 use git2::{self, Commit, Repository};
 use regex::{Regex, Match};
+use std::collections::HashSet;
 use std::fs::File;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Child;
 use std::str::FromStr;
 
+use libgitdit::attachment::Attachment;
+use libgitdit::backlinks::{Backlink, LinkKind};
+use libgitdit::changes::RefUpdate;
+use libgitdit::deps::{self, DependencyGraph};
+use libgitdit::draft::Draft;
+use libgitdit::gc::ReferenceCollectionSpec;
 use libgitdit::message::LineIteratorExt;
 use libgitdit::repository::UniqueIssues;
+use libgitdit::template::Template;
 use libgitdit::trailer::Trailer;
+use libgitdit::xref::IssueResolver;
 use libgitdit::{Issue, RepositoryExt};
 
 use error::*;
 use error::ErrorKind as EK;
 use gitext::RemotePriorization;
+use hooks;
+use notify;
 use system::{Abortable, IteratorExt, programs};
 
 /// Open the DIT repo
@@ -68,6 +79,13 @@ pub trait RepositoryUtil<'r> {
     ///
     fn cli_references(&'r self, matches: &ArgMatches) -> Vec<Commit<'r>>;
 
+    /// Store the files to attach specified on the command line as blobs
+    ///
+    /// This function returns the file names and blob ids of the files
+    /// specified via the `"attach"` field.
+    ///
+    fn cli_attachments(&self, matches: &ArgMatches) -> Vec<(String, git2::Oid)>;
+
     /// Get the path to the file usually used to edit comit messages
     fn commitmsg_edit_path(&self, matches: &ArgMatches) -> PathBuf;
 
@@ -76,12 +94,10 @@ pub trait RepositoryUtil<'r> {
     /// An editor will be spawned for editting the file specified by the path
     /// supplied. After editting, the file will be read back, stripped and
     /// checked for validity. If the comit message is valid, it will be
-    /// returned.
+    /// returned. If the editor fails or the message is not valid, an error is
+    /// returned. The file is left in place.
     ///
-    /// Note: the pathbuf is consumed since we assume that the fill will not be
-    ///       used after the commit message is read back.
-    ///
-    fn get_commit_msg(&self, path: PathBuf) -> Vec<String>;
+    fn try_get_commit_msg(&self, path: &Path) -> Result<Vec<String>>;
 
     /// Retrieve metadata from command line arguments
     ///
@@ -143,34 +159,61 @@ impl<'r> RepositoryUtil<'r> for Repository {
             .unwrap_or_default()
     }
 
-    fn get_commit_msg(&self, path: PathBuf) -> Vec<String> {
+    fn cli_attachments(&self, matches: &ArgMatches) -> Vec<(String, git2::Oid)> {
+        let mut names = HashSet::new();
+        matches
+            .values_of("attach")
+            .into_iter()
+            .flatten()
+            .map(|value| {
+                let path = Path::new(value);
+                // attachments are stored under their file names only
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .ok_or_else(|| Error::from_kind(EK::CannotAttachFile(value.to_owned())))
+                    .and_then(|name| if names.insert(name.clone()) {
+                        Ok(name)
+                    } else {
+                        Err(Error::from_kind(EK::DuplicateAttachment(name)))
+                    })
+                    .unwrap_or_abort();
+                let blob = self
+                    .blob_path(path)
+                    .chain_err(|| EK::CannotAttachFile(value.to_owned()))
+                    .unwrap_or_abort();
+                (name, blob)
+            })
+            .collect()
+    }
+
+    fn try_get_commit_msg(&self, path: &Path) -> Result<Vec<String>> {
         use system::programs::run_editor;
 
         // let the user write the message
-        if !run_editor(self.config().unwrap_or_abort(), &path)
-            .unwrap_or_abort()
+        if !run_editor(self.config()?, &path.to_path_buf())?
             .wait()
-            .unwrap_or_abort()
+            .chain_err(|| EK::WrappedIOError)?
             .success()
         {
-            Error::from_kind(EK::ChildError).log();
-            ::std::process::exit(1);
+            return Err(Error::from_kind(EK::ChildError));
         }
 
         // read the message back, check for validity
         use io::BufRead;
-        let lines : Vec<String> = io::BufReader::new(File::open(path).unwrap_or_abort())
+        let lines : Vec<String> = io::BufReader::new(File::open(path).chain_err(|| EK::WrappedIOError)?)
             .lines()
-            .abort_on_err()
+            .collect::<io::Result<Vec<String>>>()
+            .chain_err(|| EK::WrappedIOError)?
+            .into_iter()
             .stripped()
             .collect();
 
         lines
             .iter()
-            .check_message_format()
-            .unwrap_or_abort();
+            .check_message_format()?;
 
-        lines
+        Ok(lines)
     }
 
     fn prepare_trailers(&self, matches: &ArgMatches) -> Vec<Trailer> {
@@ -294,12 +337,734 @@ pub fn message_from_args(matches: &ArgMatches) -> Option<Vec<String>> {
 }
 
 
+/// Add metadata to an issue
+///
+/// This function creates a message replying to the head supplied, carrying
+/// the trailers supplied and referring to the references supplied, and makes
+/// it the issue's new local head via `create_message()`.
+///
+pub fn tag_issue(issue: &Issue,
+                 head: &mut Commit,
+                 author: &git2::Signature,
+                 committer: &git2::Signature,
+                 trailers: Vec<Trailer>,
+                 references: &[Commit]
+) -> Result<git2::Oid> {
+    use libgitdit::Message;
+
+    // construct the message
+    let message = [head.reply_subject().unwrap_or_default(), String::new()]
+        .to_vec()
+        .into_iter()
+        .chain(trailers.into_iter().map(|t| t.to_string()))
+        .collect_string();
+    let tree = issue.repo().empty_tree()?;
+    let parent_refs : Vec<&Commit> = Some(&*head).into_iter().chain(references.iter()).collect();
+
+    create_message(issue.repo(), MessageTarget::Head(issue), author, committer, &message, &tree, &parent_refs)
+}
+
+
+/// Target of a message about to be created
+///
+pub enum MessageTarget<'a, 'r: 'a> {
+    /// The message is the initial message of a new issue
+    NewIssue,
+    /// The message is added to an issue as a new leaf
+    Leaf(&'a Issue<'r>),
+    /// The message becomes the new local head of an issue
+    Head(&'a Issue<'r>),
+}
+
+
+/// Create a message
+///
+/// All messages created locally are supposed to be created via this function,
+/// which runs the dit hooks. The `dit-pre-message` hook may alter or reject
+/// the text supplied. If the message updates the head of an issue, the head is
+/// only updated if the `dit-pre-head-update` hook accepts the update. A message
+/// rejected that way is left unreferenced. Messages created are marked as read
+/// and notifications about them are emitted.
+///
+/// Returns the id of the new message, which is also the id of the new issue if
+/// a new issue is created.
+///
+pub fn create_message(repo: &Repository,
+                      target: MessageTarget,
+                      author: &git2::Signature,
+                      committer: &git2::Signature,
+                      message: &str,
+                      tree: &git2::Tree,
+                      parents: &[&Commit]
+) -> Result<git2::Oid> {
+    let (issue, parent) = match target {
+        MessageTarget::NewIssue => (None, None),
+        MessageTarget::Leaf(issue) | MessageTarget::Head(issue) =>
+            (Some(issue.id()), parents.first().map(|parent| parent.id())),
+    };
+    let message = hooks::pre_message(repo, issue, parent, message)?;
+    let id = repo.commit(None, author, committer, message.trim(), tree, parents)?;
+
+    let (issue, update) = match target {
+        MessageTarget::NewIssue => {
+            let refname = format!("refs/dit/{}/head", id);
+            hooks::pre_head_update(repo, id, &refname, git2::Oid::zero(), id)?;
+            Issue::new(repo, id)?.update_head(id, true)?;
+            (id, RefUpdate::new(refname, git2::Oid::zero(), id))
+        },
+        MessageTarget::Leaf(issue) => {
+            let refname = issue.add_leaf(id)?.name().unwrap_or_default().to_owned();
+            (issue.id(), RefUpdate::new(refname, git2::Oid::zero(), id))
+        },
+        MessageTarget::Head(issue) => {
+            let refname = format!("refs/dit/{}/head", issue.id());
+            let old = issue
+                .local_head()
+                .ok()
+                .and_then(|head| head.target())
+                .unwrap_or_else(git2::Oid::zero);
+            hooks::pre_head_update(repo, issue.id(), &refname, old, id)?;
+            issue.update_head(id, true)?;
+            (issue.id(), RefUpdate::new(refname, old, id))
+        },
+    };
+
+    Issue::new(repo, issue)?.mark_message_read(committer, id)?;
+    hooks::post_message(repo, issue, id, &update.refname)?;
+    notify::notify_updates(repo, &[update])?;
+    Ok(id)
+}
+
+
+/// Publish a draft
+///
+/// Like `Draft::publish()`, this function creates the message and discards the
+/// draft. However, the message is created via `create_message()`.
+///
+pub fn publish_draft(repo: &Repository, draft: Draft, committer: &git2::Signature) -> Result<git2::Oid> {
+    let message = draft.message()?;
+
+    let id = {
+        let issue = match draft.issue_id() {
+            Some(id) => Some(repo.find_issue(id)?),
+            None => None,
+        };
+        let target = match issue {
+            Some(ref issue) => MessageTarget::Leaf(issue),
+            None => MessageTarget::NewIssue,
+        };
+        let commit = draft.commit();
+        let parents: Vec<Commit> = commit.parents().collect();
+        let parent_refs: Vec<&Commit> = parents.iter().collect();
+        create_message(repo, target, &commit.author(), committer, &message, &commit.tree()?, &parent_refs)?
+    };
+
+    draft.discard()?;
+    Ok(id)
+}
+
+
+/// Mark an issue as a duplicate of another one
+///
+/// A message marking the duplicate is created as the duplicate's new local
+/// head, like the "tag" subcommand does. The original issue refers to the
+/// duplicate via a message with the marker as second parent.
+///
+pub fn mark_duplicate(duplicate: &Issue,
+                      original: &Issue,
+                      author: &git2::Signature,
+                      committer: &git2::Signature,
+                      prios: &RemotePriorization
+) -> Result<()> {
+    use libgitdit::Message;
+    use libgitdit::trailer::spec::{DUPLICATE_OF_SPEC, ISSUE_STATUS_SPEC};
+
+    let repo = duplicate.repo();
+    let tree = repo.empty_tree()?;
+
+    let mut duplicate_head = selected_head(duplicate, prios)?;
+    let message = [
+        duplicate_head.reply_subject().unwrap_or_default(),
+        String::new(),
+        format!("{}: duplicate", ISSUE_STATUS_SPEC.key),
+        format!("{}: {}", DUPLICATE_OF_SPEC.key, original.id()),
+    ].to_vec().into_iter().collect_string();
+    let marker = create_message(repo, MessageTarget::Head(duplicate), author, committer, &message, &tree, &[&duplicate_head])
+        .and_then(|id| repo.find_commit(id).map_err(From::from))?;
+
+    let original_head = selected_head(original, prios)?;
+    let message = [
+        format!("Duplicate: {}", duplicate.initial_message()?.summary().unwrap_or_default()),
+        String::new(),
+        format!("Issue {} was marked as a duplicate of this issue.", duplicate.id()),
+    ].to_vec().into_iter().collect_string();
+    create_message(repo, MessageTarget::Leaf(original), author, committer, &message, &tree, &[&original_head, &marker])
+        .map(|_| ())
+}
+
+
+/// Split a message off into a new issue
+///
+/// The initial message of the new issue, with the text supplied, refers to the
+/// message split off via its parent. A notice replying to the message split
+/// off refers to the new issue's initial message via its second parent.
+///
+/// Returns the id of the new issue.
+///
+pub fn split_issue(repo: &Repository,
+                   split: &Commit,
+                   author: &git2::Signature,
+                   committer: &git2::Signature,
+                   message: &str
+) -> Result<git2::Oid> {
+    use libgitdit::Message;
+    use libgitdit::trailer::spec::SPLIT_TO_SPEC;
+
+    let issue = repo.issue_with_message(split)?;
+
+    let tree = repo.empty_tree()?;
+    let new_issue = create_message(repo, MessageTarget::NewIssue, author, committer, message, &tree, &[split])?;
+    let initial = repo.find_commit(new_issue)?;
+
+    let notice = [
+        split.clone().reply_subject().unwrap_or_default(),
+        String::new(),
+        format!("The discussion was continued in issue {}.", new_issue),
+        String::new(),
+        Trailer::new(SPLIT_TO_SPEC.key, &new_issue.to_string()).to_string(),
+    ].to_vec().into_iter().collect_string();
+    let split_tree = libgitdit::attachment::message_tree(repo, Some(&split.tree()?), Vec::new())?;
+    create_message(repo, MessageTarget::Leaf(&issue), author, committer, &notice, &split_tree, &[split, &initial])?;
+
+    Ok(new_issue)
+}
+
+
+/// Get a commit message, saving the text as a draft if editing fails
+///
+/// This function behaves like `RepositoryUtil::try_get_commit_msg` but aborts
+/// if the editor fails or the message is not valid. Before aborting, the text
+/// written so far is passed to the function supplied, e.g. for saving it as a
+/// draft, unless it is blank after stripping comments or unchanged compared to
+/// the text the file was prefilled with.
+///
+pub fn get_commit_msg_or_draft<F>(repo: &Repository, path: &Path, save: F) -> Vec<String>
+    where F: Fn(&str)
+{
+    let prefilled = ::std::fs::read_to_string(path).unwrap_or_default();
+    repo.try_get_commit_msg(path)
+        .inspect_err(|_| {
+            if let Ok(text) = ::std::fs::read_to_string(path) {
+                let changed = !text.lines().stripped().eq(prefilled.lines().stripped());
+                if changed && text.lines().stripped().any(|line| !line.is_empty()) {
+                    save(&text);
+                }
+            }
+        })
+        .unwrap_or_abort()
+}
+
+
+/// Save a draft and print its name
+///
+pub fn save_draft(repo: &Repository,
+                  issue: Option<git2::Oid>,
+                  author: &git2::Signature,
+                  committer: &git2::Signature,
+                  text: &str,
+                  tree: &git2::Tree,
+                  parents: &[&Commit]
+) {
+    let draft = Draft::save(repo, issue, author, committer, text, tree, parents.iter().cloned())
+        .unwrap_or_abort();
+    println!("[dit][draft] {}", draft.name());
+}
+
+
+/// Load a message template by name
+///
+/// The template is read from the file configured via `dit.template.<name>`
+/// or, if not configured, from `.dit/templates/<name>.md` in the working
+/// directory.
+///
+pub fn load_template(repo: &Repository, name: &str) -> Template {
+    use io::BufRead;
+
+    let path = repo
+        .config()
+        .unwrap_or_abort()
+        .get_path(&format!("dit.template.{}", name))
+        .ok()
+        .or_else(|| repo
+            .workdir()
+            .map(|dir| dir.join(".dit").join("templates").join(format!("{}.md", name)))
+            .filter(|path| path.is_file())
+        )
+        .ok_or_else(|| Error::from_kind(EK::UnknownTemplate(name.to_owned())))
+        .unwrap_or_abort();
+
+    let lines: Vec<String> = File::open(path)
+        .map(io::BufReader::new)
+        .and_then(|reader| reader.lines().collect())
+        .chain_err(|| EK::UnknownTemplate(name.to_owned()))
+        .unwrap_or_abort();
+    Template::from_lines(lines)
+}
+
+
+/// Parse an age specification into a number of seconds
+///
+/// An age is specified as a number followed by an optional unit: "d" for days
+/// (the default), "w" for weeks, "m" for months or "y" for years. Months and
+/// years are approximated as 30 and 365 days, respectively.
+///
+pub fn parse_age(age: &str) -> Result<i64> {
+    let (number, days_per_unit) = match age.chars().last() {
+        Some('d') => (&age[..age.len() - 1], 1),
+        Some('w') => (&age[..age.len() - 1], 7),
+        Some('m') => (&age[..age.len() - 1], 30),
+        Some('y') => (&age[..age.len() - 1], 365),
+        _ => (age, 1),
+    };
+    number
+        .parse::<i64>()
+        .map(|number| number * days_per_unit * 24 * 60 * 60)
+        .map_err(|_| Error::from_kind(EK::MalformedAge(age.to_owned())))
+}
+
+
+/// Parse a head collection policy
+///
+/// A policy is one of "never" (the default), "backed", "always" or the name of
+/// a remote. Names of remotes which are not configured are rejected.
+///
+pub fn parse_head_policy(repo: &Repository, policy: Option<&str>) -> Result<ReferenceCollectionSpec> {
+    match policy {
+        None | Some("never")    => Ok(ReferenceCollectionSpec::Never),
+        Some("backed")          => Ok(ReferenceCollectionSpec::BackedByRemoteHead),
+        Some("always")          => Ok(ReferenceCollectionSpec::Always),
+        Some(remote)            => repo
+            .find_remote(remote)
+            .map(|_| ReferenceCollectionSpec::BackedByRemote(remote.to_owned()))
+            .chain_err(|| EK::UnknownHeadPolicy(remote.to_owned())),
+    }
+}
+
+
+/// Write an attachment to a file in a directory
+///
+/// The file is named after the attachment. Names which would place the file
+/// outside the directory are rejected. Existing files are only overwritten if
+/// `force` is set.
+///
+pub fn extract_attachment(dir: &Path, attachment: &Attachment, force: bool) -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::Component;
+
+    let name = attachment.name();
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {},
+        _ => return Err(Error::from_kind(EK::CannotExtractAttachment(name.to_owned()))),
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!force)
+        .open(dir.join(name))
+        .and_then(|mut file| file.write_all(attachment.content()))
+        .chain_err(|| EK::CannotExtractAttachment(name.to_owned()))
+}
+
+
+/// Get the dependency graph for all issues known to a repository
+///
+/// The relations are accumulated from the selected head of each issue. In
+/// addition to the graph, this function returns the set of issues considered
+/// closed, e.g. issues with a status listed in "dit.closed-statuses".
+///
+pub fn dependency_graph<'r>(repo: &'r Repository) -> (DependencyGraph<'r>, HashSet<git2::Oid>) {
+    use gitext::ReferrencesExt;
+
+    let prios = repo.remote_priorization();
+    let closed_statuses: Vec<String> = repo
+        .config()
+        .unwrap_or_abort()
+        .get_string("dit.closed-statuses")
+        .unwrap_or_else(|_| "closed".to_owned())
+        .split(',')
+        .map(|status| status.trim().to_owned())
+        .collect();
+
+    let head = |issue: &Issue<'r>| -> libgitdit::error::Result<Option<git2::Oid>> {
+        let head = issue
+            .heads()?
+            .collect::<::std::result::Result<Vec<_>, _>>()?
+            .into_iter()
+            .select_ref(&prios);
+        match head {
+            Some(head) => Ok(Some(head.peel(git2::ObjectType::Commit)?.id())),
+            None => Ok(None),
+        }
+    };
+    deps::dependency_graph(repo, repo.issues().unwrap_or_abort(), head, &closed_statuses)
+        .unwrap_or_abort()
+}
+
+
+/// Get the issues marked as duplicates of an issue
+///
+/// An issue is considered a duplicate if the "Dit-duplicate-of" trailer
+/// accumulated from its selected head refers to the issue provided.
+///
+pub fn duplicates_of<'r>(repo: &'r Repository, original: &Issue) -> Vec<Issue<'r>> {
+    use libgitdit::iter::MessagesExt;
+    use libgitdit::trailer::spec::DUPLICATE_OF_SPEC;
+    use std::collections::HashMap;
+
+    use gitext::ReferrencesExt;
+
+    let prios = repo.remote_priorization();
+    repo.issues()
+        .unwrap_or_abort()
+        .into_iter()
+        .filter(|issue| {
+            let head = issue
+                .heads()
+                .abort_on_err()
+                .select_ref(&prios)
+                .map(|head| head.peel(git2::ObjectType::Commit).unwrap_or_abort().id());
+            let acc: HashMap<_, _> = head
+                .into_iter()
+                .flat_map(|head| issue.messages_from(head).abort_on_err())
+                .accumulate_trailers(&[DUPLICATE_OF_SPEC]);
+            acc.get(DUPLICATE_OF_SPEC.key)
+                .cloned()
+                .and_then(|values| values.into_iter().next())
+                .and_then(|value| repo.revparse_single(&value.to_string()).ok())
+                .map(|object| object.id() == original.id())
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+
 /// Get an issue from a string representation
 ///
 /// This function returns an issue from a string representation.
 ///
-fn value_to_issue<'r>(repo: &'r Repository, value: &str) -> Issue<'r> {
-    let id = git2::Oid::from_str(value).unwrap_or_abort();
-    repo.find_issue(id).unwrap_or_abort()
+pub fn value_to_issue<'r>(repo: &'r Repository, value: &str) -> Issue<'r> {
+    resolve_issue(repo, value).unwrap_or_abort()
+}
+
+
+/// Resolve a string representation of an issue
+///
+/// The value may be any revision resolving to a message of the issue, e.g. the
+/// abbreviated id of the issue's initial message.
+///
+pub fn resolve_issue<'r>(repo: &'r Repository, value: &str) -> Result<Issue<'r>> {
+    let message = repo.revparse_single(value)?.peel_to_commit()?;
+    repo.issue_with_message(&message).map_err(From::from)
+}
+
+
+/// Get the selected head of an issue
+///
+pub fn selected_head<'r>(issue: &Issue<'r>, prios: &RemotePriorization) -> Result<Commit<'r>> {
+    use libgitdit::error::{Error as GitDitError, ErrorKind as GitDitErrorKind};
+
+    use gitext::ReferrencesExt;
+
+    issue
+        .heads()?
+        .collect::<::std::result::Result<Vec<_>, _>>()?
+        .select_ref(prios)
+        .ok_or_else(|| GitDitError::from_kind(GitDitErrorKind::CannotFindIssueHead(issue.id())).into())
+        .and_then(|head| head.peel_to_commit().map_err(From::from))
+}
+
+
+/// Create a resolver for references to issues in other repositories
+///
+/// In addition to the remotes, the resolver considers sibling repositories
+/// configured via `dit.sibling.<name>.path`. Siblings which cannot be opened
+/// are ignored.
+///
+pub fn issue_resolver(repo: &Repository) -> IssueResolver<'_> {
+    let mut resolver = IssueResolver::new(repo);
+
+    let config = repo.config().unwrap_or_abort();
+    let entries = config.entries(Some(r"dit\.sibling\..*\.path")).unwrap_or_abort();
+    for entry in &entries {
+        let entry = entry.unwrap_or_abort();
+        let name = entry
+            .name()
+            .unwrap_or_default()
+            .trim_start_matches("dit.sibling.")
+            .trim_end_matches(".path")
+            .to_owned();
+        match entry.value().map(Repository::open) {
+            Some(Ok(sibling)) => resolver.add_sibling(name, sibling),
+            _ => warn!("Cannot open sibling repository '{}'", name),
+        }
+    }
+
+    resolver
+}
+
+
+/// Summarize an issue in a single line
+///
+/// The summary consists of the subject of the issue's initial message and its
+/// status, if any, accumulated from the selected head.
+///
+pub fn issue_summary(issue: &Issue, prios: &RemotePriorization) -> String {
+    use libgitdit::iter::MessagesExt;
+    use libgitdit::trailer::spec::ISSUE_STATUS_SPEC;
+    use std::collections::HashMap;
+
+    use gitext::ReferrencesExt;
+
+    let subject = issue
+        .initial_message()
+        .ok()
+        .and_then(|message| message.summary().map(String::from))
+        .unwrap_or_default();
+
+    // Issues in sibling repositories may not have a head matching our
+    // priorization.
+    let head = issue
+        .heads()
+        .abort_on_err()
+        .select_ref(prios)
+        .or_else(|| issue.heads().abort_on_err().next())
+        .map(|head| head.peel(git2::ObjectType::Commit).unwrap_or_abort().id());
+    let acc: HashMap<_, _> = head
+        .into_iter()
+        .flat_map(|head| issue.messages_from(head).abort_on_err())
+        .accumulate_trailers(&[ISSUE_STATUS_SPEC]);
+    match acc.get(ISSUE_STATUS_SPEC.key).and_then(|values| values.clone().into_iter().next()) {
+        Some(status) => format!("{} [{}]", subject, status),
+        None => subject,
+    }
+}
+
+
+/// Get the metadata of an issue
+///
+/// All dit trailers present in the messages from the selected head to the
+/// initial message are accumulated. Trailers known to accumulate into lists are
+/// accumulated as such, only the latest value is kept for all other trailers.
+/// The metadata is returned ordered by key.
+///
+pub fn issue_metadata(issue: &Issue, prios: &RemotePriorization) -> Result<Vec<(String, Vec<String>)>> {
+    use libgitdit::Message;
+    use libgitdit::iter::MessagesExt;
+    use libgitdit::trailer::accumulation::AccumulationPolicy;
+    use libgitdit::trailer::spec::{self, TrailerSpec};
+    use std::collections::HashMap;
+
+    use gitext::ReferrencesExt;
+
+    let head = issue
+        .heads()?
+        .collect::<::std::result::Result<Vec<_>, _>>()?
+        .select_ref(prios);
+    let messages = match head {
+        Some(head) => issue
+            .messages_from(head.peel(git2::ObjectType::Commit)?.id())?
+            .collect::<::std::result::Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+
+    let mut keys: Vec<String> = messages
+        .iter()
+        .flat_map(|message| message.trailers().only_dit())
+        .map(|trailer| trailer.key.to_string())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    let specs: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            let accumulation = spec::KNOWN_SPECS
+                .iter()
+                .find(|spec| spec.key == key)
+                .map(|spec| spec.accumulation.clone())
+                .unwrap_or(AccumulationPolicy::Latest);
+            TrailerSpec { key: key.as_ref(), accumulation }
+        })
+        .collect();
+    let metadata: HashMap<_, _> = messages.into_iter().accumulate_trailers(&specs);
+
+    Ok(keys
+        .into_iter()
+        .map(|key| {
+            let values = metadata
+                .get(&key)
+                .map(|values| values.clone().into_iter().map(|v| v.to_string()).collect())
+                .unwrap_or_default();
+            (key, values)
+        })
+        .collect())
+}
+
+
+/// Get the context of a reply as lines
+///
+/// The context consists of the messages from the issue's initial message up to
+/// the message replied to, in chronological order, followed by the issue's
+/// current metadata accumulated from the selected head.
+///
+pub fn reply_context(issue: &Issue, parent: &Commit, prios: &RemotePriorization) -> Vec<String> {
+    use chrono::format::strftime::StrftimeItems;
+
+    use display::{FormattingToken as FT, MessageFmtToken as MFT, LineFormatter};
+
+    let formatter : Vec<FT<_,_>> = tokenvec![
+        "Message ", MFT::Id(40), FT::LineEnd,
+        "Author: ", MFT::Author, FT::LineEnd,
+        "Date: ", MFT::Date(StrftimeItems::new("%+")), FT::LineEnd,
+        FT::LineEnd,
+        MFT::Subject, FT::LineEnd,
+        FT::LineEnd,
+        MFT::Body,
+        FT::LineEnd];
+
+    let mut thread: Vec<_> = issue
+        .messages_from(parent.id())
+        .abort_on_err()
+        .collect();
+    thread.reverse();
+
+    let mut retval = vec!["Thread:".to_owned(), String::new()];
+    for message in thread {
+        retval.extend(formatter.iter().formatted_lines(message).abort_on_err());
+    }
+
+    retval.push("Metadata:".to_owned());
+    for (key, values) in issue_metadata(issue, prios).unwrap_or_abort() {
+        retval.push(format!("  {}: {}", key, values.join(", ")));
+    }
+    retval
+}
+
+
+/// Describe a backlink in a single line
+///
+/// The line consists of the referring message's id, the issue it belongs to,
+/// the kind of reference and the message's subject.
+///
+pub fn describe_backlink(repo: &Repository, link: &Backlink, id_len: usize) -> String {
+    let message = repo.find_commit(link.message).unwrap_or_abort();
+    let issue = repo
+        .issue_with_message(&message)
+        .map(|issue| format!("{0:.1$}", issue.id(), id_len))
+        .unwrap_or_else(|_| "unknown".to_owned());
+    let kind = match link.kind {
+        LinkKind::Parent => "parent",
+        LinkKind::Trailer(ref key) => key.as_str(),
+    };
+    format!("{0:.1$} (issue {2}, {3}) {4}",
+            link.message,
+            id_len,
+            issue,
+            kind,
+            message.summary().unwrap_or_default())
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Create a testing repository
+    ///
+    fn testing_repo(name: &str) -> Repository {
+        let path = ::std::env::temp_dir().join("git-dit-test").join(name);
+        fs::remove_dir_all(&path).ok();
+        Repository::init_opts(
+            path,
+            git2::RepositoryInitOptions::new().bare(true).mkdir(true)
+        ).expect("Could not open/init repository")
+    }
+
+    /// Install a hook which always fails, removing all other hooks
+    ///
+    fn install_failing_hook(repo: &Repository, name: &str) {
+        let dir = repo.path().join("hooks");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).expect("Could not create hooks directory");
+
+        let path = dir.join(name);
+        fs::write(&path, "#!/bin/sh\nexit 1\n").expect("Could not write hook");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .expect("Could not make hook executable");
+    }
+
+    /// Assert that an operation was rejected by a specific hook
+    ///
+    fn assert_rejected<T>(result: Result<T>, hook: &str) {
+        match result.map_err(|err| err.0) {
+            Err(EK::HookRejected(name)) => assert_eq!(name, hook),
+            Err(kind) => panic!("Operation failed for other reasons: {}", kind),
+            Ok(_) => panic!("Operation was not rejected by {}", hook),
+        }
+    }
+
+    // Hook tests
+
+    #[test]
+    fn rejecting_hooks() {
+        let repo = testing_repo("rejecting_hooks");
+
+        let sig = git2::Signature::now("Foo Bar", "foo.bar@example.com")
+            .expect("Could not create signature");
+        let empty_tree = repo
+            .empty_tree()
+            .expect("Could not create empty tree");
+        let prios = repo.remote_priorization();
+
+        let original = repo
+            .create_issue(&sig, &sig, "Test message 1", &empty_tree, vec![])
+            .expect("Could not create issue");
+        let original_message = original
+            .initial_message()
+            .expect("Could not retrieve initial message");
+        let duplicate = repo
+            .create_issue(&sig, &sig, "Test message 2", &empty_tree, vec![])
+            .expect("Could not create issue");
+
+        let issue_count = || repo.issues().expect("Could not retrieve issues").len();
+        let message_count = |issue: &Issue| issue
+            .messages()
+            .expect("Could not retrieve messages")
+            .count();
+
+        for hook in &["dit-pre-message", "dit-pre-head-update"] {
+            install_failing_hook(&repo, hook);
+
+            assert_rejected(split_issue(&repo, &original_message, &sig, &sig, "Test message 3"), hook);
+            assert_eq!(issue_count(), 2);
+            assert_eq!(message_count(&original), 1);
+
+            assert_rejected(mark_duplicate(&duplicate, &original, &sig, &sig, &prios), hook);
+            assert_eq!(message_count(&original), 1);
+            assert_eq!(message_count(&duplicate), 1);
+            assert_eq!(
+                duplicate.local_head().expect("Could not find head").target(),
+                Some(duplicate.id())
+            );
+        }
+    }
 }
 